@@ -0,0 +1,24 @@
+use std::process::Command;
+
+fn main() {
+    // Compiler version reported by /metrics, e.g. `1.91.0`
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|output| output.split_whitespace().nth(1).map(str::to_string))
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=RUSTC_VERSION={}", version);
+
+    // Runtime metrics only available with `--cfg tokio_unstable`, set in
+    // .cargo/config.toml
+    println!("cargo:rustc-check-cfg=cfg(tokio_unstable)");
+
+    // Build metadata passed in by the Docker build
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=BUILD_DATE");
+    println!("cargo:rerun-if-env-changed=VCS_REF");
+}
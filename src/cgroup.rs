@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use utoipa::ToSchema;
+
+/// Where the cgroup hierarchy is mounted
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Limits at or above this in cgroup v1 mean "no limit"
+const V1_UNLIMITED: u64 = 1 << 62;
+
+/// cgroup hierarchy version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CgroupVersion {
+    V1,
+    V2,
+}
+
+/// Container limits and usage read from the process's cgroup
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CgroupStats {
+    pub version: CgroupVersion,
+    /// Memory limit in bytes, none when unlimited
+    pub memory_limit: Option<u64>,
+    /// Working set in bytes: usage minus inactive file cache
+    pub memory_usage: Option<u64>,
+    /// CPUs allowed by the CFS quota, none when unlimited
+    pub cpu_quota: Option<f64>,
+    /// Enforcement periods that have elapsed
+    pub cpu_periods: Option<u64>,
+    /// Periods in which the quota was exhausted
+    pub cpu_throttled_periods: Option<u64>,
+    /// Total time spent throttled
+    pub cpu_throttled_seconds: Option<f64>,
+}
+
+/// Location of the memory and CPU controllers of a cgroup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cgroup {
+    version: CgroupVersion,
+    memory_dir: PathBuf,
+    cpu_dir: PathBuf,
+}
+
+impl Cgroup {
+    /// Find the cgroup of the current process, none when not running in one
+    pub fn detect() -> Option<Self> {
+        let membership = fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+        Self::from_root(Path::new(CGROUP_ROOT), &membership)
+    }
+
+    /// Find a cgroup under `root` using `/proc/self/cgroup` style membership
+    ///
+    /// Membership paths that do not exist under `root` fall back to `root`
+    /// itself, which is what a container with its own cgroup namespace sees.
+    pub fn from_root(root: &Path, membership: &str) -> Option<Self> {
+        if root.join("cgroup.controllers").is_file() {
+            let dir = resolve(root, membership_path(membership, |c| c.is_empty()));
+            return Some(Self {
+                version: CgroupVersion::V2,
+                memory_dir: dir.clone(),
+                cpu_dir: dir,
+            });
+        }
+
+        let memory_root = root.join("memory");
+        let cpu_root = ["cpu,cpuacct", "cpu"]
+            .iter()
+            .map(|name| root.join(name))
+            .find(|dir| dir.is_dir())?;
+        if !memory_root.is_dir() {
+            return None;
+        }
+        Some(Self {
+            version: CgroupVersion::V1,
+            memory_dir: resolve(
+                &memory_root,
+                membership_path(membership, |c| c.split(',').any(|c| c == "memory")),
+            ),
+            cpu_dir: resolve(
+                &cpu_root,
+                membership_path(membership, |c| c.split(',').any(|c| c == "cpu")),
+            ),
+        })
+    }
+
+    pub fn version(&self) -> CgroupVersion {
+        self.version
+    }
+
+    /// Read the current limits and usage
+    pub fn stats(&self) -> CgroupStats {
+        match self.version {
+            CgroupVersion::V2 => self.stats_v2(),
+            CgroupVersion::V1 => self.stats_v1(),
+        }
+    }
+
+    fn stats_v2(&self) -> CgroupStats {
+        let memory_limit = read(&self.memory_dir, "memory.max").and_then(|s| s.parse().ok());
+        let memory_current: Option<u64> =
+            read(&self.memory_dir, "memory.current").and_then(|s| s.parse().ok());
+        let inactive_file = stat(&self.memory_dir, "memory.stat", "inactive_file").unwrap_or(0);
+
+        let cpu_quota = read(&self.cpu_dir, "cpu.max").and_then(|s| {
+            let (quota, period) = s.split_once(' ')?;
+            ratio(quota.parse().ok()?, period.parse().ok()?)
+        });
+
+        CgroupStats {
+            version: CgroupVersion::V2,
+            memory_limit,
+            memory_usage: memory_current.map(|c| c.saturating_sub(inactive_file)),
+            cpu_quota,
+            cpu_periods: stat(&self.cpu_dir, "cpu.stat", "nr_periods"),
+            cpu_throttled_periods: stat(&self.cpu_dir, "cpu.stat", "nr_throttled"),
+            cpu_throttled_seconds: stat(&self.cpu_dir, "cpu.stat", "throttled_usec")
+                .map(|us| us as f64 / 1_000_000.0),
+        }
+    }
+
+    fn stats_v1(&self) -> CgroupStats {
+        let memory_limit = read(&self.memory_dir, "memory.limit_in_bytes")
+            .and_then(|s| s.parse().ok())
+            .filter(|limit| *limit < V1_UNLIMITED);
+        let memory_usage: Option<u64> =
+            read(&self.memory_dir, "memory.usage_in_bytes").and_then(|s| s.parse().ok());
+        let inactive_file =
+            stat(&self.memory_dir, "memory.stat", "total_inactive_file").unwrap_or(0);
+
+        let quota: Option<i64> =
+            read(&self.cpu_dir, "cpu.cfs_quota_us").and_then(|s| s.parse().ok());
+        let period: Option<u64> =
+            read(&self.cpu_dir, "cpu.cfs_period_us").and_then(|s| s.parse().ok());
+        let cpu_quota = match (quota, period) {
+            (Some(quota), Some(period)) if quota > 0 => ratio(quota as u64, period),
+            _ => None,
+        };
+
+        CgroupStats {
+            version: CgroupVersion::V1,
+            memory_limit,
+            memory_usage: memory_usage.map(|u| u.saturating_sub(inactive_file)),
+            cpu_quota,
+            cpu_periods: stat(&self.cpu_dir, "cpu.stat", "nr_periods"),
+            cpu_throttled_periods: stat(&self.cpu_dir, "cpu.stat", "nr_throttled"),
+            cpu_throttled_seconds: stat(&self.cpu_dir, "cpu.stat", "throttled_time")
+                .map(|ns| ns as f64 / 1_000_000_000.0),
+        }
+    }
+}
+
+/// Path of the first membership line whose controller list matches
+fn membership_path(membership: &str, controllers: impl Fn(&str) -> bool) -> &str {
+    membership
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ':');
+            let _id = parts.next()?;
+            Some((parts.next()?, parts.next()?))
+        })
+        .find(|(list, _)| controllers(list))
+        .map(|(_, path)| path)
+        .unwrap_or("/")
+}
+
+fn resolve(base: &Path, path: &str) -> PathBuf {
+    let candidate = base.join(path.trim_start_matches('/'));
+    if candidate.is_dir() {
+        candidate
+    } else {
+        base.to_path_buf()
+    }
+}
+
+fn read(dir: &Path, file: &str) -> Option<String> {
+    fs::read_to_string(dir.join(file))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+/// Value of `key` in a flat `key value` stat file
+fn stat(dir: &Path, file: &str, key: &str) -> Option<u64> {
+    read(dir, file)?.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        (name == key).then(|| value.trim().parse().ok()).flatten()
+    })
+}
+
+fn ratio(quota: u64, period: u64) -> Option<f64> {
+    (period > 0).then(|| quota as f64 / period as f64)
+}
@@ -0,0 +1,183 @@
+use axum::body::Body;
+use axum::http::{header, uri::Authority, Method, StatusCode};
+use std::time::Duration;
+
+use crate::config::{DependencyCheckConfig, DependencyCheckKind, HealthConfig};
+use crate::health::{CheckFuture, CheckOptions, CheckResult, HealthCheck, HealthRegistry};
+use crate::http_client;
+
+/// Register the dependency probes listed under `health.checks`
+pub fn register_dependency_checks(registry: &HealthRegistry, config: &HealthConfig) {
+    for check in &config.checks {
+        let options = CheckOptions {
+            critical: check.critical,
+            timeout: Duration::from_millis(check.timeout_ms),
+            cache_ttl: Duration::from_millis(check.cache_ttl_ms),
+        };
+        match check.kind {
+            DependencyCheckKind::Tcp => registry.register(TcpCheck::from_config(check), options),
+            DependencyCheckKind::Http => registry.register(HttpCheck::from_config(check), options),
+            DependencyCheckKind::Dns => registry.register(DnsCheck::from_config(check), options),
+        }
+    }
+}
+
+/// Passes when a TCP connection to `host:port` can be opened
+pub struct TcpCheck {
+    name: String,
+    address: String,
+}
+
+impl TcpCheck {
+    pub fn new(name: impl Into<String>, address: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            address: address.into(),
+        }
+    }
+
+    fn from_config(config: &DependencyCheckConfig) -> Self {
+        Self::new(&config.name, &config.target)
+    }
+}
+
+impl HealthCheck for TcpCheck {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self) -> CheckFuture<'_> {
+        Box::pin(async move {
+            let authority = match self.address.parse::<Authority>() {
+                Ok(authority) => authority,
+                Err(e) => return CheckResult::fail(format!("{}: {}", self.address, e)),
+            };
+            let Some(port) = authority.port_u16() else {
+                return CheckResult::fail(format!("{} has no port", self.address));
+            };
+            match http_client::connect(authority.host(), port).await {
+                Ok(_) => CheckResult::pass(),
+                Err(e) => CheckResult::fail(e),
+            }
+        })
+    }
+}
+
+/// Passes when a GET request returns the expected status and body
+pub struct HttpCheck {
+    name: String,
+    url: String,
+    expect_status: Option<StatusCode>,
+    expect_body: Option<String>,
+}
+
+impl HttpCheck {
+    pub fn new(name: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            url: url.into(),
+            expect_status: None,
+            expect_body: None,
+        }
+    }
+
+    /// Require this status instead of any 2xx
+    pub fn expect_status(mut self, status: StatusCode) -> Self {
+        self.expect_status = Some(status);
+        self
+    }
+
+    /// Require the response body to contain this text
+    pub fn expect_body(mut self, body: impl Into<String>) -> Self {
+        self.expect_body = Some(body.into());
+        self
+    }
+
+    fn from_config(config: &DependencyCheckConfig) -> Self {
+        Self {
+            name: config.name.clone(),
+            url: config.target.clone(),
+            expect_status: config
+                .expect_status
+                .and_then(|s| StatusCode::from_u16(s).ok()),
+            expect_body: config.expect_body.clone(),
+        }
+    }
+
+    async fn get(&self) -> Result<(StatusCode, String), String> {
+        let (status, body) = http_client::request(
+            Method::GET,
+            &self.url,
+            &[(header::USER_AGENT, "learn-rust-health")],
+            Body::empty(),
+        )
+        .await?;
+        Ok((status, String::from_utf8_lossy(&body).into_owned()))
+    }
+}
+
+impl HealthCheck for HttpCheck {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self) -> CheckFuture<'_> {
+        Box::pin(async move {
+            let (status, body) = match self.get().await {
+                Ok(response) => response,
+                Err(e) => return CheckResult::fail(format!("GET {}: {}", self.url, e)),
+            };
+            let status_ok = match self.expect_status {
+                Some(expected) => status == expected,
+                None => status.is_success(),
+            };
+            if !status_ok {
+                return CheckResult::fail(format!("GET {} returned {}", self.url, status));
+            }
+            match &self.expect_body {
+                Some(expected) if !body.contains(expected.as_str()) => CheckResult::fail(format!(
+                    "GET {} response does not contain '{}'",
+                    self.url, expected
+                )),
+                _ => CheckResult::pass(),
+            }
+        })
+    }
+}
+
+/// Passes when a hostname resolves to at least one address
+pub struct DnsCheck {
+    name: String,
+    host: String,
+}
+
+impl DnsCheck {
+    pub fn new(name: impl Into<String>, host: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            host: host.into(),
+        }
+    }
+
+    fn from_config(config: &DependencyCheckConfig) -> Self {
+        Self::new(&config.name, &config.target)
+    }
+}
+
+impl HealthCheck for DnsCheck {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self) -> CheckFuture<'_> {
+        Box::pin(async move {
+            match tokio::net::lookup_host((self.host.as_str(), 0)).await {
+                Ok(mut addrs) => match addrs.next() {
+                    Some(addr) => CheckResult::pass().with_message(addr.ip().to_string()),
+                    None => CheckResult::fail(format!("{} has no addresses", self.host)),
+                },
+                Err(e) => CheckResult::fail(format!("resolve {}: {}", self.host, e)),
+            }
+        })
+    }
+}
@@ -0,0 +1,1467 @@
+use axum::http::{uri::Authority, HeaderName, HeaderValue, Method, Uri};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+/// Directory searched for the base config and environment profiles
+const DEFAULT_CONFIG_DIR: &str = "config";
+
+/// File name, without extension, of the base config inside the config directory
+const BASE_PROFILE: &str = "base";
+
+/// Extensions tried, in order, when looking up a config file by name
+const CONFIG_EXTENSIONS: &[&str] = &["toml", "yaml", "yml"];
+
+/// Prefix for generic environment overrides, e.g. `LEARN_RUST__SERVER__PORT`
+const ENV_PREFIX: &str = "LEARN_RUST__";
+
+/// Well-known environment variables and the configuration keys they set
+const ENV_KEYS: &[(&str, &str)] = &[
+    ("HOST", "server.host"),
+    ("PORT", "server.port"),
+    ("APP_VERSION", "app.version"),
+    ("RUST_ENV", "app.environment"),
+    ("RUST_LOG", "log.filter"),
+    ("LOG_FORMAT", "log.format"),
+    ("CORS_ALLOWED_ORIGINS", "cors.allowed_origins"),
+    ("ADMIN_TOKEN", "admin.token"),
+];
+
+/// Command line flags and the configuration keys they set
+const FLAG_KEYS: &[(&str, &str)] = &[
+    ("host", "server.host"),
+    ("port", "server.port"),
+    ("app-version", "app.version"),
+    ("environment", "app.environment"),
+    ("log-filter", "log.filter"),
+    ("log-format", "log.format"),
+];
+
+/// Typed application configuration
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub runtime: RuntimeConfig,
+    pub app: AppConfig,
+    pub log: LogConfig,
+    pub cors: CorsConfig,
+    pub security_headers: SecurityHeadersConfig,
+    pub endpoints: EndpointsConfig,
+    pub rate_limit: RateLimitConfig,
+    pub reload: ReloadConfig,
+    pub admin: AdminConfig,
+    pub health: HealthConfig,
+    pub system: SystemConfig,
+    pub metrics: MetricsConfig,
+    /// Named feature flags, e.g. `features.new_checkout = true`
+    pub features: BTreeMap<String, bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// Seconds to keep serving after a shutdown signal while readiness fails
+    pub shutdown_delay: u64,
+    /// Seconds in-flight requests get to finish once the listener closes
+    pub drain_timeout: u64,
+}
+
+/// Tokio runtime the server runs on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// Threads running async tasks, 0 starts one per CPU
+    pub worker_threads: usize,
+    /// Most threads the pool running blocking code grows to
+    pub max_blocking_threads: usize,
+    /// Stack size in bytes of the worker and blocking threads
+    pub thread_stack_size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub version: String,
+    pub environment: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub filter: String,
+    pub format: LogFormat,
+}
+
+/// Output format of the log lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+/// Cross-origin resource sharing policy, `*` allows any value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    /// Preflight cache lifetime in seconds, 0 leaves it unset
+    pub max_age: u64,
+}
+
+/// Headers added to every response by the security headers middleware
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityHeadersConfig {
+    pub enabled: bool,
+    pub frame_options: String,
+    pub content_security_policy: String,
+    pub referrer_policy: String,
+    /// `Strict-Transport-Security` max-age in seconds, 0 disables the header
+    pub hsts_max_age: u64,
+}
+
+/// Optional endpoints that can be hidden per environment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EndpointsConfig {
+    pub openapi: bool,
+    /// Prometheus scrape endpoint, can be turned off when metrics are pushed
+    pub metrics: bool,
+}
+
+/// Global request rate limit, applied as a token bucket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Sustained requests per second, 0 disables rate limiting
+    pub requests_per_second: u64,
+    /// Bucket size, the most requests allowed at once, 0 uses the per-second rate
+    pub burst: u64,
+    /// Paths that are never rate limited, such as probes
+    pub exempt_paths: Vec<String>,
+}
+
+/// Runtime configuration reload
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReloadConfig {
+    /// Seconds between config file change checks, 0 only reloads on SIGHUP
+    pub poll_interval: u64,
+}
+
+/// Access to admin-only endpoints such as `/config`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Bearer token required by admin endpoints, empty disables them
+    pub token: String,
+}
+
+/// Background sampling of host metrics reported by `/healthz` and `/info`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SystemConfig {
+    /// Seconds between samples
+    pub sample_interval: u64,
+}
+
+/// Naming, labels and histogram buckets of the Prometheus metrics
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Prefix of every metric name, e.g. `learn_rust` for
+    /// `learn_rust_http_requests_total`
+    pub namespace: String,
+    /// Prefix added after the namespace
+    pub subsystem: String,
+    /// Label every metric with the `service`, `environment` and `version`
+    /// of the application
+    pub app_labels: bool,
+    /// Extra labels added to every metric, e.g. `labels.team = "payments"`
+    pub labels: BTreeMap<String, String>,
+    pub buckets: BucketsConfig,
+    /// Seconds between pushes to the sinks and to OTLP when
+    /// `OTEL_EXPORTER_OTLP_ENDPOINT` is set
+    pub export_interval: u64,
+    /// Destinations the metrics are pushed to, for environments that can't
+    /// be scraped
+    pub sinks: Vec<MetricsSinkConfig>,
+}
+
+/// Upper bounds of the buckets of each histogram, in increasing order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BucketsConfig {
+    pub http_request_duration_seconds: Vec<f64>,
+    pub http_request_size_bytes: Vec<f64>,
+    pub http_response_size_bytes: Vec<f64>,
+}
+
+/// A Pushgateway or StatsD agent the metrics are pushed to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsSinkConfig {
+    pub kind: MetricsSinkKind,
+    /// An `http://` URL for the Pushgateway, `host:port` for StatsD
+    pub target: String,
+    /// Pushgateway job the metrics are grouped under
+    pub job: String,
+}
+
+/// Protocol used by a metrics sink
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricsSinkKind {
+    #[default]
+    Pushgateway,
+    Statsd,
+    /// StatsD with labels sent as DogStatsD tags
+    Dogstatsd,
+}
+
+/// Smallest `runtime.thread_stack_size`, below which threads overflow their stack
+const MIN_THREAD_STACK_SIZE: usize = 64 * 1024;
+
+/// Labels set by the metrics themselves, which constant labels must not use
+const METRIC_LABELS: &[&str] = &[
+    "method",
+    "route",
+    "status",
+    "phase",
+    "resource",
+    "commit",
+    "build_date",
+    "rustc",
+    "worker",
+];
+
+/// Labels added by `metrics.app_labels`
+pub const APP_LABELS: &[&str] = &["service", "environment", "version"];
+
+/// Health checks reported by `/readyz` and `/healthz`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// Dependency probes registered at startup
+    pub checks: Vec<DependencyCheckConfig>,
+    pub thresholds: ThresholdsConfig,
+}
+
+/// Resource levels that degrade the service or make it unhealthy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThresholdsConfig {
+    /// Memory in use as a percentage of the container limit or host memory
+    pub memory_percent: Threshold,
+    /// One minute load average per available CPU
+    pub cpu_load: Threshold,
+    /// Open file descriptors of the process
+    pub open_fds: Threshold,
+    /// Milliseconds the runtime woke up late for a timer
+    pub event_loop_lag_ms: Threshold,
+    /// Fraction below a threshold a value must drop to before recovering
+    pub hysteresis: f64,
+}
+
+/// Degraded and unhealthy levels of a resource, 0 disables a level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Threshold {
+    pub degraded: f64,
+    pub unhealthy: f64,
+}
+
+impl Threshold {
+    pub fn is_enabled(&self) -> bool {
+        self.degraded > 0.0 || self.unhealthy > 0.0
+    }
+}
+
+impl Default for ThresholdsConfig {
+    fn default() -> Self {
+        Self {
+            memory_percent: Threshold::default(),
+            cpu_load: Threshold::default(),
+            open_fds: Threshold::default(),
+            event_loop_lag_ms: Threshold::default(),
+            hysteresis: 0.1,
+        }
+    }
+}
+
+/// A TCP, HTTP or DNS probe of something the service depends on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DependencyCheckConfig {
+    pub name: String,
+    pub kind: DependencyCheckKind,
+    /// `host:port` for TCP, an `http://` URL for HTTP, a hostname for DNS
+    pub target: String,
+    /// A failing critical check fails readiness, others only degrade it
+    pub critical: bool,
+    /// Milliseconds before the probe fails
+    pub timeout_ms: u64,
+    /// Milliseconds to reuse the last result, 0 probes on every request
+    pub cache_ttl_ms: u64,
+    /// HTTP status the response must have, any 2xx when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect_status: Option<u16>,
+    /// Text the HTTP response body must contain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect_body: Option<String>,
+}
+
+/// Protocol used by a dependency probe
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyCheckKind {
+    #[default]
+    Tcp,
+    Http,
+    Dns,
+}
+
+impl Default for DependencyCheckConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            kind: DependencyCheckKind::default(),
+            target: String::new(),
+            critical: true,
+            timeout_ms: 2000,
+            cache_ttl_ms: 0,
+            expect_status: None,
+            expect_body: None,
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 8080,
+            shutdown_delay: 0,
+            drain_timeout: 25,
+        }
+    }
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            worker_threads: 0,
+            max_blocking_threads: 512,
+            thread_stack_size: 2 * 1024 * 1024,
+        }
+    }
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            version: "0.0.1".to_string(),
+            environment: "development".to_string(),
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            filter: "learn_rust=debug,tower_http=debug".to_string(),
+            format: LogFormat::default(),
+        }
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec!["*".to_string()],
+            allowed_methods: vec!["*".to_string()],
+            allowed_headers: vec!["*".to_string()],
+            max_age: 0,
+        }
+    }
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            frame_options: "DENY".to_string(),
+            content_security_policy: "default-src 'self'".to_string(),
+            referrer_policy: "strict-origin-when-cross-origin".to_string(),
+            hsts_max_age: 0,
+        }
+    }
+}
+
+impl Default for EndpointsConfig {
+    fn default() -> Self {
+        Self {
+            openapi: true,
+            metrics: true,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 0,
+            burst: 0,
+            exempt_paths: vec![
+                "/ping".to_string(),
+                "/healthz".to_string(),
+                "/livez".to_string(),
+                "/readyz".to_string(),
+                "/startupz".to_string(),
+                "/metrics".to_string(),
+            ],
+        }
+    }
+}
+
+impl Default for SystemConfig {
+    fn default() -> Self {
+        Self { sample_interval: 5 }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            namespace: String::new(),
+            subsystem: String::new(),
+            app_labels: true,
+            labels: BTreeMap::new(),
+            buckets: BucketsConfig::default(),
+            export_interval: 60,
+            sinks: Vec::new(),
+        }
+    }
+}
+
+impl Default for MetricsSinkConfig {
+    fn default() -> Self {
+        Self {
+            kind: MetricsSinkKind::default(),
+            target: String::new(),
+            job: "learn-rust".to_string(),
+        }
+    }
+}
+
+impl Default for BucketsConfig {
+    fn default() -> Self {
+        // Body sizes from 64 bytes to 16 MiB
+        let sizes: Vec<f64> = (0..10).map(|i| 64.0 * 4f64.powi(i)).collect();
+        Self {
+            http_request_duration_seconds: prometheus::DEFAULT_BUCKETS.to_vec(),
+            http_request_size_bytes: sizes.clone(),
+            http_response_size_bytes: sizes,
+        }
+    }
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self { poll_interval: 10 }
+    }
+}
+
+impl Config {
+    /// Load configuration from the process environment and command line
+    pub fn load() -> Result<Self, ConfigError> {
+        ConfigLoader::from_env().load()
+    }
+
+    /// Whether a named feature flag is switched on
+    pub fn feature(&self, name: &str) -> bool {
+        self.features.get(name).copied().unwrap_or(false)
+    }
+
+    /// Every value as a dotted key, with secrets redacted
+    pub fn redacted_entries(&self) -> BTreeMap<String, Value> {
+        let mut entries = BTreeMap::new();
+        flatten(
+            "",
+            &serde_json::to_value(self).expect("config serializes"),
+            &mut entries,
+        );
+        entries
+            .into_iter()
+            .map(|(key, value)| {
+                let value = redact_value(&key, &value);
+                (key, value)
+            })
+            .collect()
+    }
+
+    /// Keys whose values differ from `other`, with the old and new value
+    pub fn diff(&self, other: &Config) -> Vec<(String, Value, Value)> {
+        let (mut old, mut new) = (BTreeMap::new(), BTreeMap::new());
+        flatten(
+            "",
+            &serde_json::to_value(self).expect("config serializes"),
+            &mut old,
+        );
+        flatten(
+            "",
+            &serde_json::to_value(other).expect("config serializes"),
+            &mut new,
+        );
+
+        let keys: std::collections::BTreeSet<_> = old.keys().chain(new.keys()).cloned().collect();
+        keys.into_iter()
+            .filter_map(|key| {
+                let before = old.remove(&key).unwrap_or(Value::Null);
+                let after = new.remove(&key).unwrap_or(Value::Null);
+                (before != after).then_some((key, before, after))
+            })
+            .collect()
+    }
+
+    /// Address the server binds to
+    pub fn bind_addr(&self) -> String {
+        format!("{}:{}", self.server.host, self.server.port)
+    }
+
+    /// Check the configuration for values that would fail at runtime
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let host = &self.server.host;
+        if host.is_empty() {
+            return Err(ConfigError::invalid("server.host", "must not be empty"));
+        }
+        if host.parse::<IpAddr>().is_err() && !is_valid_hostname(host) {
+            return Err(ConfigError::invalid(
+                "server.host",
+                format!("'{}' is not an IP address or hostname", host),
+            ));
+        }
+        if self.server.port == 0 {
+            return Err(ConfigError::invalid(
+                "server.port",
+                "must be between 1 and 65535",
+            ));
+        }
+        if self.runtime.max_blocking_threads == 0 {
+            return Err(ConfigError::invalid(
+                "runtime.max_blocking_threads",
+                "must be above 0",
+            ));
+        }
+        if self.runtime.thread_stack_size < MIN_THREAD_STACK_SIZE {
+            return Err(ConfigError::invalid(
+                "runtime.thread_stack_size",
+                format!("must be at least {} bytes", MIN_THREAD_STACK_SIZE),
+            ));
+        }
+        if self.app.version.trim().is_empty() {
+            return Err(ConfigError::invalid("app.version", "must not be empty"));
+        }
+        if !is_valid_profile_name(&self.app.environment) {
+            return Err(ConfigError::invalid(
+                "app.environment",
+                "must be non-empty and contain only letters, digits, '-' or '_'",
+            ));
+        }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log.filter) {
+            return Err(ConfigError::invalid(
+                "log.filter",
+                format!("'{}' is not a valid filter: {}", self.log.filter, e),
+            ));
+        }
+        self.cors.validate()?;
+        self.security_headers.validate()?;
+        self.health.validate()?;
+        self.metrics.validate()?;
+        Ok(())
+    }
+}
+
+impl CorsConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        let check = |key: &str, values: &[String], valid: &dyn Fn(&str) -> bool| match values
+            .iter()
+            .find(|v| v.as_str() != "*" && !valid(v))
+        {
+            Some(bad) => Err(ConfigError::invalid(key, format!("'{}' is not valid", bad))),
+            None => Ok(()),
+        };
+        check("cors.allowed_origins", &self.allowed_origins, &|v| {
+            HeaderValue::from_str(v).is_ok()
+        })?;
+        check("cors.allowed_methods", &self.allowed_methods, &|v| {
+            Method::from_bytes(v.as_bytes()).is_ok()
+        })?;
+        check("cors.allowed_headers", &self.allowed_headers, &|v| {
+            HeaderName::from_bytes(v.as_bytes()).is_ok()
+        })
+    }
+}
+
+impl SecurityHeadersConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        for (key, value) in [
+            ("security_headers.frame_options", &self.frame_options),
+            (
+                "security_headers.content_security_policy",
+                &self.content_security_policy,
+            ),
+            ("security_headers.referrer_policy", &self.referrer_policy),
+        ] {
+            if HeaderValue::from_str(value).is_err() {
+                return Err(ConfigError::invalid(
+                    key,
+                    format!("'{}' is not a valid header value", value),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl HealthConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.thresholds.validate()?;
+        let mut names = BTreeSet::new();
+        for (i, check) in self.checks.iter().enumerate() {
+            let key = |field: &str| format!("health.checks[{}].{}", i, field);
+            if check.name.trim().is_empty() {
+                return Err(ConfigError::invalid(&key("name"), "must not be empty"));
+            }
+            if !names.insert(check.name.as_str()) {
+                return Err(ConfigError::invalid(
+                    &key("name"),
+                    format!("'{}' is used by more than one check", check.name),
+                ));
+            }
+            if check.timeout_ms == 0 {
+                return Err(ConfigError::invalid(&key("timeout_ms"), "must be above 0"));
+            }
+            let target = &check.target;
+            match check.kind {
+                DependencyCheckKind::Tcp => validate_host_port(&key("target"), target)?,
+                DependencyCheckKind::Http => validate_http_url(&key("target"), target)?,
+                DependencyCheckKind::Dns if !is_valid_hostname(target) => {
+                    return Err(ConfigError::invalid(
+                        &key("target"),
+                        format!("'{}' is not a hostname", target),
+                    ));
+                }
+                DependencyCheckKind::Dns => {}
+            }
+            if check.kind != DependencyCheckKind::Http
+                && (check.expect_status.is_some() || check.expect_body.is_some())
+            {
+                return Err(ConfigError::invalid(
+                    &key("kind"),
+                    "expect_status and expect_body only apply to http checks",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MetricsConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.export_interval == 0 {
+            return Err(ConfigError::invalid(
+                "metrics.export_interval",
+                "must be above 0",
+            ));
+        }
+        for (key, prefix) in [
+            ("metrics.namespace", &self.namespace),
+            ("metrics.subsystem", &self.subsystem),
+        ] {
+            if !prefix.is_empty() && !is_valid_metric_name(prefix) {
+                return Err(ConfigError::invalid(
+                    key,
+                    format!(
+                        "'{}' must start with a letter or '_' and contain only letters, digits or '_'",
+                        prefix
+                    ),
+                ));
+            }
+        }
+        for name in self.labels.keys() {
+            let key = format!("metrics.labels.{}", name);
+            if !is_valid_metric_name(name) || name.starts_with("__") {
+                return Err(ConfigError::invalid(
+                    &key,
+                    format!("'{}' is not a valid label name", name),
+                ));
+            }
+            if METRIC_LABELS.contains(&name.as_str())
+                || (self.app_labels && APP_LABELS.contains(&name.as_str()))
+            {
+                return Err(ConfigError::invalid(
+                    &key,
+                    format!("'{}' is already used by the metrics", name),
+                ));
+            }
+        }
+        for (name, buckets) in [
+            (
+                "http_request_duration_seconds",
+                &self.buckets.http_request_duration_seconds,
+            ),
+            (
+                "http_request_size_bytes",
+                &self.buckets.http_request_size_bytes,
+            ),
+            (
+                "http_response_size_bytes",
+                &self.buckets.http_response_size_bytes,
+            ),
+        ] {
+            validate_buckets(buckets)
+                .map_err(|e| ConfigError::invalid(&format!("metrics.buckets.{}", name), e))?;
+        }
+        for (i, sink) in self.sinks.iter().enumerate() {
+            let key = |field: &str| format!("metrics.sinks[{}].{}", i, field);
+            match sink.kind {
+                MetricsSinkKind::Pushgateway => validate_http_url(&key("target"), &sink.target)?,
+                MetricsSinkKind::Statsd | MetricsSinkKind::Dogstatsd => {
+                    validate_host_port(&key("target"), &sink.target)?
+                }
+            }
+            if sink.kind == MetricsSinkKind::Pushgateway && !is_valid_profile_name(&sink.job) {
+                return Err(ConfigError::invalid(
+                    &key("job"),
+                    "must be non-empty and contain only letters, digits, '-' or '_'",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ThresholdsConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if !(0.0..1.0).contains(&self.hysteresis) {
+            return Err(ConfigError::invalid(
+                "health.thresholds.hysteresis",
+                "must be at least 0 and below 1",
+            ));
+        }
+        for (name, threshold) in [
+            ("memory_percent", &self.memory_percent),
+            ("cpu_load", &self.cpu_load),
+            ("open_fds", &self.open_fds),
+            ("event_loop_lag_ms", &self.event_loop_lag_ms),
+        ] {
+            if threshold.degraded < 0.0 || threshold.unhealthy < 0.0 {
+                return Err(ConfigError::invalid(
+                    &format!("health.thresholds.{}", name),
+                    "levels must not be negative",
+                ));
+            }
+            if threshold.degraded > 0.0
+                && threshold.unhealthy > 0.0
+                && threshold.unhealthy < threshold.degraded
+            {
+                return Err(ConfigError::invalid(
+                    &format!("health.thresholds.{}.unhealthy", name),
+                    format!(
+                        "{} is below the degraded level {}",
+                        threshold.unhealthy, threshold.degraded
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn is_valid_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Check histogram bucket bounds are non-empty, finite and increasing
+pub fn validate_buckets(buckets: &[f64]) -> Result<(), String> {
+    if buckets.is_empty() {
+        return Err("buckets must not be empty".to_string());
+    }
+    if buckets.iter().any(|bound| !bound.is_finite()) {
+        return Err("bucket bounds must be finite".to_string());
+    }
+    if buckets.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err("bucket bounds must be increasing".to_string());
+    }
+    Ok(())
+}
+
+/// Check `target` is a plain `http://` URL with a host
+fn validate_http_url(key: &str, target: &str) -> Result<(), ConfigError> {
+    let uri = target.parse::<Uri>().ok();
+    if uri.as_ref().and_then(Uri::scheme_str) == Some("https") {
+        return Err(ConfigError::invalid(
+            key,
+            format!(
+                "'{}' uses https, which is not supported, use an http:// URL",
+                target
+            ),
+        ));
+    }
+    let valid = uri.is_some_and(|uri| uri.scheme_str() == Some("http") && uri.host().is_some());
+    if !valid {
+        return Err(ConfigError::invalid(
+            key,
+            format!("'{}' is not an http:// URL", target),
+        ));
+    }
+    Ok(())
+}
+
+/// Check `target` is a `host:port` address, with IPv6 hosts in brackets
+fn validate_host_port(key: &str, target: &str) -> Result<(), ConfigError> {
+    let valid = target.parse::<Authority>().is_ok_and(|authority| {
+        !authority.host().is_empty()
+            && !authority.as_str().contains('@')
+            && authority.port_u16().is_some()
+    });
+    if !valid {
+        return Err(ConfigError::invalid(
+            key,
+            format!("'{}' is not a host:port address", target),
+        ));
+    }
+    Ok(())
+}
+
+fn is_valid_hostname(host: &str) -> bool {
+    host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Where a configuration value came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    File { path: PathBuf },
+    Env { name: String },
+    Flag { name: String },
+}
+
+impl ConfigSource {
+    fn file(path: &Path) -> Self {
+        ConfigSource::File {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Short name of the layer: `default`, `file`, `env` or `flag`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ConfigSource::Default => "default",
+            ConfigSource::File { .. } => "file",
+            ConfigSource::Env { .. } => "env",
+            ConfigSource::Flag { .. } => "flag",
+        }
+    }
+
+    /// The file, variable or flag that set the value, if any
+    pub fn origin(&self) -> Option<String> {
+        match self {
+            ConfigSource::Default => None,
+            ConfigSource::File { path } => Some(path.display().to_string()),
+            ConfigSource::Env { name } => Some(name.clone()),
+            ConfigSource::Flag { name } => Some(format!("--{}", name)),
+        }
+    }
+}
+
+/// Source of every configuration value, keyed by dotted path
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigSources(BTreeMap<String, ConfigSource>);
+
+impl ConfigSources {
+    /// Source of a dotted key, `Default` when it was never overridden
+    ///
+    /// List items such as `health.checks[0].target` fall back to the source
+    /// of the list itself, since a layer replaces a list as a whole.
+    pub fn get(&self, key: &str) -> &ConfigSource {
+        std::iter::once(key)
+            .chain(key.rmatch_indices('[').map(|(i, _)| &key[..i]))
+            .find_map(|key| self.0.get(key))
+            .unwrap_or(&ConfigSource::Default)
+    }
+
+    /// Record the source of a single key
+    pub fn set(&mut self, key: impl Into<String>, source: ConfigSource) {
+        let key = key.into();
+        self.0
+            .retain(|other, _| !other.strip_prefix(&key).is_some_and(|r| r.starts_with('[')));
+        self.0.insert(key, source);
+    }
+
+    fn record_layer(&mut self, layer: &Value, source: &ConfigSource) {
+        let mut keys = BTreeMap::new();
+        flatten("", layer, &mut keys);
+        for key in keys.into_keys() {
+            self.set(key, source.clone());
+        }
+    }
+}
+
+/// Placeholder shown instead of secret values
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Key name fragments that mark a value as secret
+const SECRET_MARKERS: &[&str] = &["token", "secret", "password", "api_key", "credential"];
+
+/// Whether the dotted key holds a secret that must never be shown or logged
+pub fn is_secret_key(key: &str) -> bool {
+    let leaf = key.rsplit('.').next().unwrap_or(key).to_ascii_lowercase();
+    SECRET_MARKERS.iter().any(|marker| leaf.contains(marker))
+}
+
+/// Replace a secret value with [`REDACTED`], leaving unset (empty) values visible
+///
+/// URLs under any key lose their `user:pass@` part and query string, which
+/// commonly carry credentials.
+pub fn redact_value(key: &str, value: &Value) -> Value {
+    let empty = matches!(value, Value::Null) || value.as_str().is_some_and(str::is_empty);
+    if is_secret_key(key) && !empty {
+        Value::String(REDACTED.to_string())
+    } else if let Some(url) = value.as_str() {
+        Value::String(strip_url_credentials(url))
+    } else {
+        value.clone()
+    }
+}
+
+/// Drop the userinfo, query and fragment of a `scheme://` URL
+fn strip_url_credentials(value: &str) -> String {
+    let Some(scheme_end) = value.find("://").map(|i| i + 3) else {
+        return value.to_string();
+    };
+    let rest = &value[scheme_end..];
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let host = rest[..authority_end]
+        .rsplit_once('@')
+        .map_or(&rest[..authority_end], |(_, host)| host);
+    let path = &rest[authority_end..];
+    let path = &path[..path.find(['?', '#']).unwrap_or(path.len())];
+    format!("{}{}{}", &value[..scheme_end], host, path)
+}
+
+/// Error raised while loading or validating configuration
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The configuration file could not be parsed
+    Parse { path: PathBuf, message: String },
+    /// A key does not exist in the configuration
+    UnknownKey { key: String, origin: String },
+    /// A value is present but not acceptable
+    Invalid { key: String, message: String },
+    /// A command line argument is malformed
+    Args(String),
+}
+
+impl ConfigError {
+    fn invalid(key: &str, message: impl Into<String>) -> Self {
+        ConfigError::Invalid {
+            key: key.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => {
+                write!(f, "cannot read config file {}: {}", path.display(), source)
+            }
+            ConfigError::Parse { path, message } => {
+                write!(
+                    f,
+                    "cannot parse config file {}: {}",
+                    path.display(),
+                    message
+                )
+            }
+            ConfigError::UnknownKey { key, origin } => {
+                write!(f, "unknown configuration key '{}' (from {})", key, origin)
+            }
+            ConfigError::Invalid { key, message } => {
+                write!(f, "invalid value for '{}': {}", key, message)
+            }
+            ConfigError::Args(message) => write!(f, "invalid command line: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Builds a [`Config`] from layered sources
+///
+/// Precedence, lowest to highest: defaults, base config file, environment
+/// profile, environment variables, command line flags.
+///
+/// The base file is taken from `--config` or `CONFIG_FILE`, falling back to
+/// `base.{toml,yaml,yml}` in the config directory. The profile is
+/// `<environment>.{toml,yaml,yml}` in the config directory, where the
+/// environment is resolved from the other layers (`RUST_ENV` usually). The
+/// config directory defaults to `config` and is set with `--config-dir` or
+/// `CONFIG_DIR`. Missing profile and base files are skipped.
+#[derive(Debug, Default, Clone)]
+pub struct ConfigLoader {
+    file: Option<PathBuf>,
+    dir: Option<PathBuf>,
+    env: Vec<(String, String)>,
+    args: Vec<String>,
+}
+
+impl ConfigLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loader reading the process environment and command line
+    pub fn from_env() -> Self {
+        Self::new()
+            .env(std::env::vars())
+            .args(std::env::args().skip(1))
+    }
+
+    /// Use an explicit configuration file
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.file = Some(path.into());
+        self
+    }
+
+    /// Use an explicit directory for the base config and profiles
+    pub fn dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.dir = Some(path.into());
+        self
+    }
+
+    /// Environment variables to read overrides from
+    pub fn env<I, K, V>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env = vars
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+        self
+    }
+
+    /// Command line arguments, without the program name
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Merge every layer, then deserialize and validate the result
+    pub fn load(&self) -> Result<Config, ConfigError> {
+        self.load_with_sources().map(|(config, _)| config)
+    }
+
+    /// Like [`ConfigLoader::load`], also reporting where each value came from
+    pub fn load_with_sources(&self) -> Result<(Config, ConfigSources), ConfigError> {
+        let defaults = serde_json::to_value(Config::default()).expect("config serializes");
+        let flags = parse_args(&self.args)?;
+        let dir = self.config_dir(&flags);
+        let mut sources = ConfigSources::default();
+        sources.record_layer(&defaults, &ConfigSource::Default);
+
+        let base_path = self
+            .config_file(&flags)
+            .or_else(|| find_profile(&dir, BASE_PROFILE));
+        let base = match &base_path {
+            Some(path) => read_file(path)?,
+            None => Value::Object(Map::new()),
+        };
+
+        // Environment variables and flags win over files, so resolve them
+        // first to learn which profile to load
+        let mut overrides = Value::Object(Map::new());
+        let mut override_sources = Vec::new();
+
+        for (name, value) in &self.env {
+            let Some(key) = env_key(name) else {
+                continue;
+            };
+            let origin = format!("environment variable {}", name);
+            set_key(&mut overrides, &defaults, &key, value, &origin)?;
+            override_sources.push((key, ConfigSource::Env { name: name.clone() }));
+        }
+
+        for (flag, value) in &flags.values {
+            let origin = format!("flag --{}", flag);
+            let (key, value) = if flag == "set" {
+                value.split_once('=').ok_or_else(|| {
+                    ConfigError::Args(format!("--set expects key=value, got '{}'", value))
+                })?
+            } else {
+                let key = FLAG_KEYS
+                    .iter()
+                    .find(|(name, _)| name == flag)
+                    .map(|(_, key)| *key)
+                    .ok_or_else(|| ConfigError::Args(format!("unknown flag --{}", flag)))?;
+                (key, value.as_str())
+            };
+            set_key(&mut overrides, &defaults, key, value, &origin)?;
+            override_sources.push((key.to_string(), ConfigSource::Flag { name: flag.clone() }));
+        }
+
+        let environment = [&overrides, &base, &defaults]
+            .iter()
+            .find_map(|layer| layer.pointer("/app/environment").and_then(Value::as_str))
+            .unwrap_or_default()
+            .to_string();
+        if !is_valid_profile_name(&environment) {
+            return Err(ConfigError::invalid(
+                "app.environment",
+                format!("'{}' cannot be used as a profile name", environment),
+            ));
+        }
+        let profile_path = find_profile(&dir, &environment);
+        let profile = match &profile_path {
+            Some(path) => read_file(path)?,
+            None => Value::Object(Map::new()),
+        };
+
+        for (path, layer) in [(&base_path, &base), (&profile_path, &profile)] {
+            if let Some(path) = path {
+                sources.record_layer(layer, &ConfigSource::file(path));
+            }
+        }
+        for (key, source) in override_sources {
+            sources.set(key, source);
+        }
+
+        let mut merged = defaults;
+        merge(&mut merged, base);
+        merge(&mut merged, profile);
+        merge(&mut merged, overrides);
+
+        let config: Config =
+            serde_path_to_error::deserialize(merged).map_err(|e| ConfigError::Invalid {
+                key: e.path().to_string(),
+                message: e.into_inner().to_string(),
+            })?;
+        config.validate()?;
+        Ok((config, sources))
+    }
+
+    /// Every file that can contribute to the configuration for `environment`,
+    /// whether or not it exists yet
+    pub fn watched_files(&self, environment: &str) -> Vec<PathBuf> {
+        let flags = parse_args(&self.args).unwrap_or_default();
+        let dir = self.config_dir(&flags);
+        let profiles = [BASE_PROFILE, environment].into_iter().flat_map(|name| {
+            CONFIG_EXTENSIONS
+                .iter()
+                .map(|ext| dir.join(format!("{}.{}", name, ext)))
+                .collect::<Vec<_>>()
+        });
+        self.config_file(&flags)
+            .into_iter()
+            .chain(profiles)
+            .collect()
+    }
+
+    fn config_file(&self, flags: &Flags) -> Option<PathBuf> {
+        flags
+            .config
+            .clone()
+            .or_else(|| self.file.clone())
+            .or_else(|| self.env_var("CONFIG_FILE").map(PathBuf::from))
+    }
+
+    fn config_dir(&self, flags: &Flags) -> PathBuf {
+        flags
+            .config_dir
+            .clone()
+            .or_else(|| self.dir.clone())
+            .or_else(|| self.env_var("CONFIG_DIR").map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_DIR))
+    }
+
+    fn env_var(&self, name: &str) -> Option<&str> {
+        self.env
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Parsed command line flags in the order they were given
+#[derive(Debug, Default)]
+struct Flags {
+    config: Option<PathBuf>,
+    config_dir: Option<PathBuf>,
+    values: Vec<(String, String)>,
+}
+
+fn parse_args(args: &[String]) -> Result<Flags, ConfigError> {
+    let mut flags = Flags::default();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        let name = arg
+            .strip_prefix("--")
+            .ok_or_else(|| ConfigError::Args(format!("unexpected argument '{}'", arg)))?;
+
+        let (name, value) = match name.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| ConfigError::Args(format!("--{} expects a value", name)))?;
+                (name.to_string(), value.clone())
+            }
+        };
+
+        match name.as_str() {
+            "config" => flags.config = Some(PathBuf::from(value)),
+            "config-dir" => flags.config_dir = Some(PathBuf::from(value)),
+            _ => flags.values.push((name, value)),
+        }
+    }
+
+    Ok(flags)
+}
+
+fn env_key(name: &str) -> Option<String> {
+    if let Some(rest) = name.strip_prefix(ENV_PREFIX) {
+        return Some(rest.to_ascii_lowercase().replace("__", "."));
+    }
+    ENV_KEYS
+        .iter()
+        .find(|(var, _)| *var == name)
+        .map(|(_, key)| key.to_string())
+}
+
+/// Find `<dir>/<name>.<ext>` for the first supported extension that exists
+fn find_profile(dir: &Path, name: &str) -> Option<PathBuf> {
+    CONFIG_EXTENSIONS
+        .iter()
+        .map(|ext| dir.join(format!("{}.{}", name, ext)))
+        .find(|path| path.is_file())
+}
+
+fn read_file(path: &Path) -> Result<Value, ConfigError> {
+    let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let parse_err = |message: String| ConfigError::Parse {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let value: Value = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&contents).map_err(|e| parse_err(e.to_string()))?,
+        Some("yaml") | Some("yml") => {
+            serde_yaml::from_str(&contents).map_err(|e| parse_err(e.to_string()))?
+        }
+        _ => {
+            return Err(parse_err(
+                "unsupported format, expected .toml, .yaml or .yml".to_string(),
+            ))
+        }
+    };
+
+    match value {
+        Value::Object(_) => Ok(value),
+        Value::Null => Ok(Value::Object(Map::new())),
+        _ => Err(parse_err("top level must be a table".to_string())),
+    }
+}
+
+/// Deep-merge `overlay` into `base`, replacing everything but tables
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Set a dotted key in a layer from a string, typed after the default value
+fn set_key(
+    layer: &mut Value,
+    defaults: &Value,
+    key: &str,
+    raw: &str,
+    origin: &str,
+) -> Result<(), ConfigError> {
+    let unknown = || ConfigError::UnknownKey {
+        key: key.to_string(),
+        origin: origin.to_string(),
+    };
+
+    // Maps such as `features` are empty by default, so their entries have no
+    // template and the value type is inferred from the raw string instead
+    let parts: Vec<&str> = key.split('.').collect();
+    let mut template = defaults;
+    for (i, part) in parts.iter().enumerate() {
+        match template.get(part) {
+            Some(next) => template = next,
+            None if i + 1 == parts.len() && template.as_object().is_some_and(Map::is_empty) => {
+                template = &Value::Null;
+            }
+            None => return Err(unknown()),
+        }
+    }
+    if template.is_object() {
+        return Err(unknown());
+    }
+
+    let value = typed_value(template, raw).ok_or_else(|| ConfigError::Invalid {
+        key: key.to_string(),
+        message: format!(
+            "'{}' from {} is not a valid {}",
+            raw,
+            origin,
+            kind(template)
+        ),
+    })?;
+
+    let (parents, leaf) = match key.rsplit_once('.') {
+        Some((parents, leaf)) => (parents.split('.').collect::<Vec<_>>(), leaf),
+        None => (Vec::new(), key),
+    };
+    let mut target = layer;
+    for part in parents {
+        target = target
+            .as_object_mut()
+            .ok_or_else(unknown)?
+            .entry(part)
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    target
+        .as_object_mut()
+        .ok_or_else(unknown)?
+        .insert(leaf.to_string(), value);
+    Ok(())
+}
+
+fn typed_value(template: &Value, raw: &str) -> Option<Value> {
+    match template {
+        Value::Bool(_) => raw.parse::<bool>().ok().map(Value::Bool),
+        Value::Number(n) if n.is_u64() => raw.parse::<u64>().ok().map(Value::from),
+        Value::Number(n) if n.is_i64() => raw.parse::<i64>().ok().map(Value::from),
+        Value::Number(_) => raw.parse::<f64>().ok().map(Value::from),
+        // Elements take the type of the default list's elements, strings
+        // when it has none
+        Value::Array(items) => {
+            let element = match items.first() {
+                Some(first @ (Value::Bool(_) | Value::Number(_))) => first,
+                _ => &Value::String(String::new()),
+            };
+            raw.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| typed_value(element, s))
+                .collect::<Option<Vec<_>>>()
+                .map(Value::Array)
+        }
+        Value::Null => Some(
+            raw.parse::<bool>()
+                .map(Value::Bool)
+                .or_else(|_| raw.parse::<u64>().map(Value::from))
+                .or_else(|_| raw.parse::<f64>().map(Value::from))
+                .unwrap_or_else(|_| Value::String(raw.to_string())),
+        ),
+        _ => Some(Value::String(raw.to_string())),
+    }
+}
+
+/// Flatten a value into dotted keys, keeping lists as single leaves
+fn flatten(prefix: &str, value: &Value, out: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(map) if !map.is_empty() || prefix.is_empty() => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&key, value, out);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (index, item) in items.iter().enumerate() {
+                flatten(&format!("{}[{}]", prefix, index), item, out);
+            }
+        }
+        _ => {
+            out.insert(prefix.to_string(), value.clone());
+        }
+    }
+}
+
+fn kind(template: &Value) -> &'static str {
+    match template {
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::Array(_) => "list",
+        _ => "string",
+    }
+}
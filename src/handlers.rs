@@ -1,8 +1,17 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
 use std::sync::Arc;
-use sysinfo::System;
 
+use crate::health::{plaintext_report, CheckReport, HealthStatus};
 use crate::models::*;
+use crate::selection::{SectionQuery, Selection};
+
+/// Media type of the IETF health check response format
+pub const HEALTH_JSON: &str = "application/health+json";
 
 /// Root endpoint handler - Returns welcome message with API documentation
 #[utoipa::path(
@@ -41,6 +50,21 @@ pub async fn index(State(_state): State<Arc<AppState>>) -> impl IntoResponse {
                 method: "GET".to_string(),
                 description: "Health check endpoint".to_string(),
             },
+            Endpoint {
+                path: "/livez".to_string(),
+                method: "GET".to_string(),
+                description: "Liveness probe".to_string(),
+            },
+            Endpoint {
+                path: "/readyz".to_string(),
+                method: "GET".to_string(),
+                description: "Readiness probe".to_string(),
+            },
+            Endpoint {
+                path: "/startupz".to_string(),
+                method: "GET".to_string(),
+                description: "Startup probe".to_string(),
+            },
             Endpoint {
                 path: "/info".to_string(),
                 method: "GET".to_string(),
@@ -56,6 +80,11 @@ pub async fn index(State(_state): State<Arc<AppState>>) -> impl IntoResponse {
                 method: "POST".to_string(),
                 description: "Echo back the request body".to_string(),
             },
+            Endpoint {
+                path: "/config".to_string(),
+                method: "GET".to_string(),
+                description: "Effective configuration (admin only)".to_string(),
+            },
             Endpoint {
                 path: "/metrics".to_string(),
                 method: "GET".to_string(),
@@ -85,105 +114,340 @@ pub async fn ping() -> impl IntoResponse {
     (StatusCode::OK, "pong")
 }
 
+/// Liveness probe - Passes while the process is able to answer requests
+#[utoipa::path(
+    get,
+    path = "/livez",
+    responses(
+        (status = 200, description = "Process is alive", body = ApiResponse<ProbeData>)
+    ),
+    tag = "health"
+)]
+pub async fn livez(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    probe(&state, true)
+}
+
+/// Readiness probe - Passes while the service should receive traffic
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    params(HealthQuery),
+    responses(
+        (status = 200, description = "Ready to serve traffic", content(
+            (ApiResponse<ProbeData> = "application/json"),
+            (HealthJson = "application/health+json"),
+            (String = "text/plain")
+        )),
+        (status = 503, description = "Starting, draining or a critical check is failing", content(
+            (ApiResponse<ProbeData> = "application/json"),
+            (HealthJson = "application/health+json"),
+            (String = "text/plain")
+        ))
+    ),
+    tag = "health"
+)]
+pub async fn readyz(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HealthQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let report = state.health_report().await;
+    let status_code = probe_status_code(report.status.is_ready());
+
+    match HealthFormat::negotiate(&query, &headers) {
+        HealthFormat::Plaintext => plaintext("readyz", status_code, report.status, &report.checks),
+        HealthFormat::HealthJson => health_json(
+            status_code,
+            HealthJson::new(&state.app_info, report.status, &report.checks).observe(
+                "uptime",
+                state.uptime(),
+                "s",
+            ),
+        ),
+        HealthFormat::Api => {
+            let data = ProbeData {
+                status: report.status,
+                uptime: state.uptime(),
+                checks: report.checks,
+            };
+            (status_code, Json(ApiResponse::success(data))).into_response()
+        }
+    }
+}
+
+/// Startup probe - Passes once initialization has finished
+#[utoipa::path(
+    get,
+    path = "/startupz",
+    responses(
+        (status = 200, description = "Initialization finished", body = ApiResponse<ProbeData>),
+        (status = 503, description = "Still starting", body = ApiResponse<ProbeData>)
+    ),
+    tag = "health"
+)]
+pub async fn startupz(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let started = state.is_started();
+    probe(&state, started)
+}
+
+fn probe(state: &AppState, pass: bool) -> (StatusCode, Json<ApiResponse<ProbeData>>) {
+    let data = ProbeData {
+        status: state.health_status(),
+        uptime: state.uptime(),
+        checks: Vec::new(),
+    };
+    (probe_status_code(pass), Json(ApiResponse::success(data)))
+}
+
+/// Representation requested for a health response
+enum HealthFormat {
+    /// `ApiResponse` JSON, the default
+    Api,
+    /// kube-apiserver style `[+]check ok` lines, selected by `?verbose`
+    Plaintext,
+    /// IETF health check format, selected by `Accept: application/health+json`
+    HealthJson,
+}
+
+impl HealthFormat {
+    fn negotiate(query: &HealthQuery, headers: &HeaderMap) -> Self {
+        if accepts(headers, HEALTH_JSON) {
+            HealthFormat::HealthJson
+        } else if query.verbose.is_some() {
+            HealthFormat::Plaintext
+        } else {
+            HealthFormat::Api
+        }
+    }
+}
+
+/// Whether the `Accept` headers list `media_type`, unless refused with `q=0`
+pub fn accepts(headers: &HeaderMap, media_type: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|range| {
+            let mut params = range.split(';').map(str::trim);
+            let listed = params
+                .next()
+                .is_some_and(|media| media.eq_ignore_ascii_case(media_type));
+            listed
+                && !params.any(|param| {
+                    param.split_once('=').is_some_and(|(name, q)| {
+                        name.trim().eq_ignore_ascii_case("q") && q.trim().parse() == Ok(0.0)
+                    })
+                })
+        })
+}
+
+fn plaintext(
+    probe: &str,
+    status_code: StatusCode,
+    status: HealthStatus,
+    checks: &[CheckReport],
+) -> Response {
+    (
+        status_code,
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        plaintext_report(probe, status, checks),
+    )
+        .into_response()
+}
+
+fn health_json(status_code: StatusCode, body: HealthJson) -> Response {
+    (
+        status_code,
+        [(header::CONTENT_TYPE, HEALTH_JSON)],
+        Json(body),
+    )
+        .into_response()
+}
+
+fn probe_status_code(pass: bool) -> StatusCode {
+    if pass {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
 /// Health check endpoint - Returns detailed health information
 #[utoipa::path(
     get,
     path = "/healthz",
+    params(HealthQuery, SectionQuery),
     responses(
-        (status = 200, description = "Health status with system metrics", body = ApiResponse<HealthData>)
+        (status = 200, description = "Health status with system metrics", content(
+            (ApiResponse<HealthData> = "application/json"),
+            (HealthJson = "application/health+json"),
+            (String = "text/plain")
+        )),
+        (status = 503, description = "Service is starting, draining or a critical check is failing", content(
+            (ApiResponse<HealthData> = "application/json"),
+            (HealthJson = "application/health+json"),
+            (String = "text/plain")
+        ))
     ),
     tag = "health"
 )]
-pub async fn healthz(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    let mut sys = System::new_all();
-    sys.refresh_all();
-
-    let uptime = state
-        .start_time
-        .elapsed()
-        .map(|d| d.as_secs_f64())
-        .unwrap_or(0.0);
-
-    let total_memory = sys.total_memory();
-    let available_memory = sys.available_memory();
-    let used_memory = total_memory - available_memory;
-    let memory_percent = if total_memory > 0 {
-        (used_memory as f64 / total_memory as f64) * 100.0
-    } else {
-        0.0
+pub async fn healthz(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HealthQuery>,
+    Query(sections): Query<SectionQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let format = HealthFormat::negotiate(&query, &headers);
+    let selection = match format {
+        HealthFormat::Api => match Selection::parse(&sections, HealthData::SECTIONS) {
+            Ok(selection) => selection,
+            Err(e) => return bad_request(e),
+        },
+        _ => Selection::all(),
     };
 
+    // Checks always run since they decide the overall status
+    let report = state.health_report().await;
+    let status = report.status;
+    let snapshot =
+        (selection.wants("memory") || selection.wants("system")).then(|| state.system.snapshot());
+
     let health = HealthData {
-        status: "healthy".to_string(),
-        uptime,
-        memory: MemoryInfo {
-            total: total_memory,
-            available: available_memory,
-            used: used_memory,
-            percent: memory_percent,
-        },
-        system: SystemInfo {
-            os: std::env::consts::OS.to_string(),
-            arch: std::env::consts::ARCH.to_string(),
-            cpu_count: sys.cpus().len(),
-            hostname: System::host_name().unwrap_or_else(|| "unknown".to_string()),
-        },
+        status,
+        uptime: selection.wants("uptime").then(|| state.uptime()),
+        memory: snapshot
+            .as_ref()
+            .filter(|_| selection.wants("memory"))
+            .map(|snapshot| snapshot.memory()),
+        system: snapshot
+            .as_ref()
+            .filter(|_| selection.wants("system"))
+            .map(|snapshot| SystemInfo {
+                os: std::env::consts::OS.to_string(),
+                arch: std::env::consts::ARCH.to_string(),
+                cpu_count: snapshot.cpu_count,
+                hostname: snapshot.hostname.clone(),
+                cgroup: snapshot.cgroup.clone(),
+                sample_age: snapshot.age(),
+            }),
+        checks: selection.wants("checks").then_some(report.checks),
     };
 
-    Json(ApiResponse::success(health))
+    let status_code = probe_status_code(status.is_ready());
+    match format {
+        HealthFormat::Plaintext => plaintext(
+            "healthz",
+            status_code,
+            status,
+            health.checks.as_deref().unwrap_or_default(),
+        ),
+        HealthFormat::HealthJson => {
+            health_json(status_code, health.to_health_json(&state.app_info))
+        }
+        HealthFormat::Api => selected(status_code, &selection, health, &["status"]),
+    }
 }
 
 /// Info endpoint - Returns application and system information
 #[utoipa::path(
     get,
     path = "/info",
+    params(SectionQuery),
     responses(
-        (status = 200, description = "Detailed system and application info", body = ApiResponse<InfoData>)
+        (status = 200, description = "Detailed system and application info", body = ApiResponse<InfoData>),
+        (status = 400, description = "Unknown section or field selected", body = ApiResponse<String>)
     ),
     tag = "info"
 )]
-pub async fn info(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    let mut sys = System::new_all();
-    sys.refresh_all();
-
-    let uptime = state
-        .start_time
-        .elapsed()
-        .map(|d| d.as_secs_f64())
-        .unwrap_or(0.0);
-
-    let total_memory = sys.total_memory();
-    let available_memory = sys.available_memory();
-    let used_memory = total_memory - available_memory;
-    let memory_percent = if total_memory > 0 {
-        (used_memory as f64 / total_memory as f64) * 100.0
-    } else {
-        0.0
+pub async fn info(
+    State(state): State<Arc<AppState>>,
+    Query(sections): Query<SectionQuery>,
+) -> Response {
+    let selection = match Selection::parse(&sections, InfoData::SECTIONS) {
+        Ok(selection) => selection,
+        Err(e) => return bad_request(e),
     };
+    let snapshot =
+        (selection.wants("system") || selection.wants("process")).then(|| state.system.snapshot());
 
     let info = InfoData {
-        application: state.app_info.clone(),
-        system: DetailedSystemInfo {
-            os: std::env::consts::OS.to_string(),
-            arch: std::env::consts::ARCH.to_string(),
-            hostname: System::host_name().unwrap_or_else(|| "unknown".to_string()),
-            cpu_count: sys.cpus().len(),
-            uptime,
-            memory: MemoryInfo {
-                total: total_memory,
-                available: available_memory,
-                used: used_memory,
-                percent: memory_percent,
-            },
-        },
-        environment: EnvironmentInfo {
-            rust_version: rustc_version_runtime::version().to_string(),
-            port: std::env::var("PORT").unwrap_or_else(|_| "8080".to_string()),
-            host: std::env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
-        },
+        application: selection
+            .wants("application")
+            .then(|| state.app_info.clone()),
+        system: snapshot
+            .as_ref()
+            .filter(|_| selection.wants("system"))
+            .map(|snapshot| DetailedSystemInfo {
+                os: std::env::consts::OS.to_string(),
+                arch: std::env::consts::ARCH.to_string(),
+                hostname: snapshot.hostname.clone(),
+                cpu_count: snapshot.cpu_count,
+                uptime: state.uptime(),
+                memory: snapshot.memory(),
+                cgroup: snapshot.cgroup.clone(),
+                sample_age: snapshot.age(),
+            }),
+        process: snapshot
+            .as_ref()
+            .filter(|_| selection.wants("process"))
+            .map(|snapshot| process_info(snapshot)),
+        runtime: selection.wants("runtime").then(|| state.runtime.clone()),
+        environment: selection.wants("environment").then(|| {
+            let config = state.config();
+            EnvironmentInfo {
+                rust_version: rustc_version_runtime::version().to_string(),
+                port: config.server.port.to_string(),
+                host: config.server.host.clone(),
+                config_generation: state.config_generation(),
+            }
+        }),
     };
 
-    Json(ApiResponse::success(info))
+    selected(StatusCode::OK, &selection, info, &[])
+}
+
+/// Respond with only the selected fields of `data`
+fn selected<T: serde::Serialize>(
+    status_code: StatusCode,
+    selection: &Selection,
+    data: T,
+    always: &[&str],
+) -> Response {
+    let value = serde_json::to_value(data).expect("response serializes");
+    match selection.apply(value, always) {
+        Ok(value) => (status_code, Json(ApiResponse::success(value))).into_response(),
+        Err(e) => bad_request(e),
+    }
+}
+
+fn bad_request(message: String) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ApiResponse::<()>::error(message)),
+    )
+        .into_response()
+}
+
+fn process_info(snapshot: &crate::system::SystemSnapshot) -> ProcessInfo {
+    let process = &snapshot.process;
+    let runtime = tokio::runtime::Handle::current().metrics();
+    ProcessInfo {
+        pid: process.pid,
+        rss: process.rss,
+        virtual_memory: process.virtual_memory,
+        cpu_percent: process.cpu_percent,
+        threads: process.threads,
+        open_fds: snapshot.open_fds,
+        start_time: chrono::DateTime::from_timestamp(process.start_time as i64, 0)
+            .unwrap_or_default()
+            .to_rfc3339(),
+        tokio: TokioRuntimeInfo {
+            workers: runtime.num_workers(),
+            alive_tasks: runtime.num_alive_tasks(),
+            global_queue_depth: runtime.global_queue_depth(),
+        },
+    }
 }
 
 /// Version endpoint - Returns version information
@@ -198,8 +462,8 @@ pub async fn info(State(state): State<Arc<AppState>>) -> impl IntoResponse {
 pub async fn version_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
     let version = VersionData {
         version: state.app_info.version.clone(),
-        build_date: option_env!("BUILD_DATE").unwrap_or("unknown").to_string(),
-        commit: option_env!("VCS_REF").unwrap_or("unknown").to_string(),
+        build_date: BUILD_DATE.to_string(),
+        commit: COMMIT.to_string(),
     };
 
     Json(ApiResponse::success(version))
@@ -224,3 +488,36 @@ pub async fn echo(Json(payload): Json<EchoRequest>) -> impl IntoResponse {
 
     Json(ApiResponse::success(response))
 }
+
+/// Config endpoint - Returns the effective configuration and where each value came from
+#[utoipa::path(
+    get,
+    path = "/config",
+    responses(
+        (status = 200, description = "Effective configuration with secrets redacted", body = ApiResponse<ConfigData>),
+        (status = 401, description = "Missing or invalid admin token"),
+        (status = 404, description = "Admin endpoints are disabled")
+    ),
+    security(("admin_token" = [])),
+    tag = "admin"
+)]
+pub async fn config_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let generation = state.config_generation();
+    let (config, sources) = state.config_with_sources();
+
+    let values = config
+        .redacted_entries()
+        .into_iter()
+        .map(|(key, value)| {
+            let source = sources.get(&key);
+            ConfigValue {
+                value,
+                source: source.kind().to_string(),
+                origin: source.origin(),
+                key,
+            }
+        })
+        .collect();
+
+    Json(ApiResponse::success(ConfigData { generation, values }))
+}
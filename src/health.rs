@@ -0,0 +1,392 @@
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Health reported by the probes and `/healthz`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    /// Initialization has not finished yet
+    Starting,
+    /// Able to serve traffic
+    Ready,
+    /// Serving, but something is not working as it should
+    Degraded,
+    /// A critical check is failing
+    Unhealthy,
+    /// Shutting down, in-flight requests are being drained
+    Draining,
+}
+
+impl HealthStatus {
+    /// Whether the service should receive traffic in this state
+    pub fn is_ready(self) -> bool {
+        matches!(self, HealthStatus::Ready | HealthStatus::Degraded)
+    }
+
+    /// Equivalent `pass`, `warn` or `fail` status of the health+json format
+    pub fn check_status(self) -> CheckStatus {
+        match self {
+            HealthStatus::Ready => CheckStatus::Pass,
+            HealthStatus::Degraded => CheckStatus::Warn,
+            _ => CheckStatus::Fail,
+        }
+    }
+}
+
+/// Lifecycle phase of the service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Phase {
+    Starting = 0,
+    Ready = 1,
+    Draining = 2,
+}
+
+/// Lock-free holder of the current [`Phase`]
+#[derive(Debug)]
+pub struct Lifecycle(AtomicU8);
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Self(AtomicU8::new(Phase::Starting as u8))
+    }
+
+    pub fn phase(&self) -> Phase {
+        match self.0.load(Ordering::SeqCst) {
+            0 => Phase::Starting,
+            1 => Phase::Ready,
+            _ => Phase::Draining,
+        }
+    }
+
+    /// Move from starting to ready, never leaving the draining phase
+    pub fn mark_started(&self) {
+        let _ = self.0.compare_exchange(
+            Phase::Starting as u8,
+            Phase::Ready as u8,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+    }
+
+    pub fn start_draining(&self) {
+        self.0.store(Phase::Draining as u8, Ordering::SeqCst);
+    }
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Outcome of a single health check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Result returned by [`HealthCheck::check`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    pub status: CheckStatus,
+    pub message: Option<String>,
+    /// Time spent running the check, filled in by the registry
+    pub duration: Duration,
+}
+
+impl CheckResult {
+    pub fn pass() -> Self {
+        Self::new(CheckStatus::Pass, None)
+    }
+
+    pub fn warn(message: impl Into<String>) -> Self {
+        Self::new(CheckStatus::Warn, Some(message.into()))
+    }
+
+    pub fn fail(message: impl Into<String>) -> Self {
+        Self::new(CheckStatus::Fail, Some(message.into()))
+    }
+
+    /// Attach a message to the result
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    fn new(status: CheckStatus, message: Option<String>) -> Self {
+        Self {
+            status,
+            message,
+            duration: Duration::ZERO,
+        }
+    }
+}
+
+/// Boxed future returned by [`HealthCheck::check`]
+pub type CheckFuture<'a> = Pin<Box<dyn Future<Output = CheckResult> + Send + 'a>>;
+
+/// A named check reported by `/healthz`
+///
+/// Implementations only need to probe their dependency; timeouts, caching
+/// and timing are handled by the [`HealthRegistry`].
+pub trait HealthCheck: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn check(&self) -> CheckFuture<'_>;
+}
+
+/// How a registered check is run and aggregated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckOptions {
+    /// A failing critical check makes the service unhealthy, a failing
+    /// non-critical one only degrades it
+    pub critical: bool,
+    /// Checks running longer than this fail
+    pub timeout: Duration,
+    /// Reuse the last result for this long, zero runs the check every time
+    pub cache_ttl: Duration,
+}
+
+impl CheckOptions {
+    pub fn critical() -> Self {
+        Self {
+            critical: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn non_critical() -> Self {
+        Self {
+            critical: false,
+            ..Self::default()
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        Self {
+            critical: true,
+            timeout: Duration::from_secs(5),
+            cache_ttl: Duration::ZERO,
+        }
+    }
+}
+
+/// Per-check entry of a [`HealthReport`]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CheckReport {
+    pub name: String,
+    pub status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub duration_ms: f64,
+    pub critical: bool,
+    /// Whether the result was served from the cache
+    pub cached: bool,
+}
+
+/// Aggregated result of every registered check
+#[derive(Debug, Clone)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+    pub checks: Vec<CheckReport>,
+}
+
+struct Registered {
+    check: Arc<dyn HealthCheck>,
+    options: CheckOptions,
+    last: Mutex<Option<(Instant, CheckResult)>>,
+}
+
+impl Registered {
+    async fn run(&self) -> CheckReport {
+        let cached = self
+            .last
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|(at, _)| at.elapsed() < self.options.cache_ttl)
+            .map(|(_, result)| result.clone());
+
+        let (result, cached) = match cached {
+            Some(result) => (result, true),
+            None => {
+                let started = Instant::now();
+                let mut result =
+                    match tokio::time::timeout(self.options.timeout, self.check.check()).await {
+                        Ok(result) => result,
+                        Err(_) => CheckResult::fail(format!(
+                            "timed out after {}ms",
+                            self.options.timeout.as_millis()
+                        )),
+                    };
+                result.duration = started.elapsed();
+                *self.last.lock().unwrap() = Some((Instant::now(), result.clone()));
+                (result, false)
+            }
+        };
+
+        CheckReport {
+            name: self.check.name().to_string(),
+            status: result.status,
+            message: result.message,
+            duration_ms: result.duration.as_secs_f64() * 1000.0,
+            critical: self.options.critical,
+            cached,
+        }
+    }
+}
+
+/// Registry of the health checks reported by `/healthz`
+#[derive(Default)]
+pub struct HealthRegistry {
+    checks: RwLock<Vec<Arc<Registered>>>,
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a check, replacing any existing check with the same name
+    pub fn register(&self, check: impl HealthCheck + 'static, options: CheckOptions) {
+        let entry = Arc::new(Registered {
+            check: Arc::new(check),
+            options,
+            last: Mutex::new(None),
+        });
+        let mut checks = self.checks.write().unwrap();
+        match checks
+            .iter_mut()
+            .find(|existing| existing.check.name() == entry.check.name())
+        {
+            Some(existing) => *existing = entry,
+            None => checks.push(entry),
+        }
+    }
+
+    /// Remove a check by name, returning whether it was registered
+    pub fn unregister(&self, name: &str) -> bool {
+        let mut checks = self.checks.write().unwrap();
+        let before = checks.len();
+        checks.retain(|entry| entry.check.name() != name);
+        checks.len() != before
+    }
+
+    /// Names of the registered checks in registration order
+    pub fn names(&self) -> Vec<String> {
+        self.checks
+            .read()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.check.name().to_string())
+            .collect()
+    }
+
+    /// Run every check concurrently and aggregate the results
+    ///
+    /// Any failing critical check makes the service unhealthy. Failing
+    /// non-critical checks and warnings degrade it.
+    pub async fn run(&self) -> HealthReport {
+        let entries = self.checks.read().unwrap().clone();
+        let handles: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                let entry = entry.clone();
+                tokio::spawn(async move { entry.run().await })
+            })
+            .collect();
+
+        let mut checks = Vec::with_capacity(handles.len());
+        for (entry, handle) in entries.iter().zip(handles) {
+            checks.push(handle.await.unwrap_or_else(|e| CheckReport {
+                name: entry.check.name().to_string(),
+                status: CheckStatus::Fail,
+                message: Some(format!("check panicked: {}", e)),
+                duration_ms: 0.0,
+                critical: entry.options.critical,
+                cached: false,
+            }));
+        }
+
+        HealthReport {
+            status: aggregate(&checks),
+            checks,
+        }
+    }
+}
+
+fn aggregate(checks: &[CheckReport]) -> HealthStatus {
+    if checks
+        .iter()
+        .any(|c| c.critical && c.status == CheckStatus::Fail)
+    {
+        HealthStatus::Unhealthy
+    } else if checks.iter().any(|c| c.status != CheckStatus::Pass) {
+        HealthStatus::Degraded
+    } else {
+        HealthStatus::Ready
+    }
+}
+
+/// Render checks in the kube-apiserver plaintext style
+///
+/// ```text
+/// [+]lifecycle ok
+/// [-]database failed: connection refused
+/// readyz check failed
+/// ```
+pub fn plaintext_report(probe: &str, status: HealthStatus, checks: &[CheckReport]) -> String {
+    let mut out = String::new();
+    match status {
+        HealthStatus::Starting | HealthStatus::Draining => {
+            let phase = serde_json::to_value(status).expect("status serializes");
+            out.push_str(&format!(
+                "[-]lifecycle failed: {}\n",
+                phase.as_str().unwrap_or_default()
+            ));
+        }
+        _ => out.push_str("[+]lifecycle ok\n"),
+    }
+    for check in checks {
+        let line = match (check.status, &check.message) {
+            (CheckStatus::Pass, _) => format!("[+]{} ok", check.name),
+            (CheckStatus::Warn, Some(message)) => format!("[+]{} warn: {}", check.name, message),
+            (CheckStatus::Warn, None) => format!("[+]{} warn", check.name),
+            (CheckStatus::Fail, Some(message)) => format!("[-]{} failed: {}", check.name, message),
+            (CheckStatus::Fail, None) => format!("[-]{} failed", check.name),
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out.push_str(&format!(
+        "{} check {}\n",
+        probe,
+        if status.is_ready() {
+            "passed"
+        } else {
+            "failed"
+        }
+    ));
+    out
+}
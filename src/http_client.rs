@@ -0,0 +1,61 @@
+use axum::body::{Body, Bytes};
+use axum::http::{header, HeaderName, Method, Request, StatusCode, Uri};
+use http_body_util::BodyExt;
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpStream;
+
+/// Send one request over a new HTTP/1.1 connection and read the whole
+/// response
+///
+/// Only plain `http://` URLs are supported, there is no TLS. Callers bound
+/// the time taken, e.g. with the check or push timeout.
+pub async fn request(
+    method: Method,
+    url: &str,
+    headers: &[(HeaderName, &str)],
+    body: Body,
+) -> Result<(StatusCode, Bytes), String> {
+    let uri: Uri = url.parse().map_err(|e| format!("invalid url: {}", e))?;
+    let host = uri.host().ok_or("url has no host")?;
+    let port = uri.port_u16().unwrap_or(80);
+
+    let stream = connect(host, port).await?;
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(|e| e.to_string())?;
+    tokio::spawn(connection);
+
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let mut request = Request::builder().method(method).uri(path).header(
+        header::HOST,
+        uri.authority().map(|a| a.as_str()).unwrap_or(host),
+    );
+    for (name, value) in headers {
+        request = request.header(name, *value);
+    }
+    let request = request.body(body).map_err(|e| e.to_string())?;
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| e.to_string())?
+        .to_bytes();
+    Ok((status, body))
+}
+
+/// Open a TCP connection to `host` and `port`, where `host` may be a
+/// bracketed IPv6 literal as written in URLs and `host:port` addresses
+pub async fn connect(host: &str, port: u16) -> Result<TcpStream, String> {
+    let ip_or_name = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
+    TcpStream::connect((ip_or_name, port))
+        .await
+        .map_err(|e| format!("connect to {}:{}: {}", host, port, e))
+}
@@ -0,0 +1,195 @@
+//! learn-rust service library.
+//!
+//! Exposes the router composition used by the binary so that tests and
+//! downstream services share the exact same routes and middleware stack.
+
+use axum::{
+    routing::{get, post, MethodRouter},
+    Router,
+};
+use std::sync::Arc;
+use tower_http::trace::{DefaultMakeSpan, TraceLayer};
+
+pub mod cgroup;
+pub mod checks;
+pub mod config;
+pub mod handlers;
+pub mod health;
+pub mod http_client;
+pub mod metrics;
+pub mod middleware;
+pub mod models;
+pub mod openapi;
+pub mod openmetrics;
+#[cfg(feature = "telemetry")]
+pub mod otlp;
+pub mod platform;
+pub mod reload;
+pub mod resources;
+pub mod runtime;
+pub mod selection;
+pub mod shutdown;
+pub mod sinks;
+pub mod system;
+pub mod telemetry;
+
+#[cfg(test)]
+mod tests;
+
+pub use config::Config;
+use handlers::*;
+pub use models::AppState;
+
+/// Build the application router with the default routes and middleware stack
+pub fn build_app(config: Config) -> Result<Router, metrics::MetricsError> {
+    AppBuilder::new(config).build()
+}
+
+/// Builder for the application router
+///
+/// Starts from the built-in routes and lets callers mount extra routes and
+/// layers before the standard middleware stack (CORS, tracing, security
+/// headers and metrics) is applied on top.
+pub struct AppBuilder {
+    state: Arc<AppState>,
+    router: Router<Arc<AppState>>,
+    defer_startup: bool,
+}
+
+impl AppBuilder {
+    pub fn new(config: Config) -> Self {
+        Self::with_state(Arc::new(AppState::new(config)))
+    }
+
+    /// Start from an existing application state
+    pub fn with_state(state: Arc<AppState>) -> Self {
+        let mut router = Router::new()
+            .route("/", get(index))
+            .route("/ping", get(ping))
+            .route("/healthz", get(healthz))
+            .route("/livez", get(livez))
+            .route("/readyz", get(readyz))
+            .route("/startupz", get(startupz))
+            .route("/info", get(info))
+            .route("/version", get(version_handler))
+            .route("/echo", post(echo))
+            .route(
+                "/config",
+                get(config_handler).route_layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    middleware::admin_auth,
+                )),
+            );
+
+        checks::register_dependency_checks(&state.health, &state.config().health);
+        resources::register_resource_checks(
+            &state.health,
+            &state.resources,
+            &state.config().health.thresholds,
+        );
+
+        if state.config().endpoints.metrics {
+            router = router.route("/metrics", get(metrics::metrics_handler));
+        }
+        if state.config().endpoints.openapi {
+            router = router.route("/openapi.json", get(openapi::openapi_handler));
+        }
+
+        Self {
+            state,
+            router,
+            defer_startup: false,
+        }
+    }
+
+    /// Shared application state the router will be built with
+    pub fn state(&self) -> Arc<AppState> {
+        self.state.clone()
+    }
+
+    /// Keep the startup probe failing until [`AppState::mark_started`] is called
+    ///
+    /// By default the router is ready as soon as it is built.
+    pub fn defer_startup(mut self) -> Self {
+        self.defer_startup = true;
+        self
+    }
+
+    /// Register a health check reported by `/readyz` and `/healthz`
+    pub fn health_check(
+        self,
+        check: impl health::HealthCheck + 'static,
+        options: health::CheckOptions,
+    ) -> Self {
+        self.state.health.register(check, options);
+        self
+    }
+
+    /// Mount an additional route
+    pub fn route(mut self, path: &str, method_router: MethodRouter<Arc<AppState>>) -> Self {
+        self.router = self.router.route(path, method_router);
+        self
+    }
+
+    /// Merge another router into the application
+    pub fn merge(mut self, router: Router<Arc<AppState>>) -> Self {
+        self.router = self.router.merge(router);
+        self
+    }
+
+    /// Nest a router under a path prefix
+    pub fn nest(mut self, path: &str, router: Router<Arc<AppState>>) -> Self {
+        self.router = self.router.nest(path, router);
+        self
+    }
+
+    /// Apply a layer to every route registered so far
+    ///
+    /// Custom layers always sit inside the standard middleware stack.
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: tower::Layer<axum::routing::Route> + Clone + Send + 'static,
+        L::Service: tower::Service<axum::extract::Request> + Clone + Send + 'static,
+        <L::Service as tower::Service<axum::extract::Request>>::Response:
+            axum::response::IntoResponse + 'static,
+        <L::Service as tower::Service<axum::extract::Request>>::Error:
+            Into<std::convert::Infallible> + 'static,
+        <L::Service as tower::Service<axum::extract::Request>>::Future: Send + 'static,
+    {
+        self.router = self.router.layer(layer);
+        self
+    }
+
+    /// Finish the router, applying the standard middleware stack
+    ///
+    /// Fails when the metrics can't be registered, such as a custom metric
+    /// created beforehand clashing with a built-in one.
+    pub fn build(self) -> Result<Router, metrics::MetricsError> {
+        metrics::init_metrics(&self.state.config().metrics, &self.state.app_info)?;
+        if !self.defer_startup {
+            self.state.mark_started();
+        }
+
+        Ok(self
+            .router
+            .layer(axum::middleware::from_fn_with_state(
+                self.state.clone(),
+                middleware::rate_limit,
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                self.state.clone(),
+                middleware::cors,
+            ))
+            // Inside the request span, so durations carry its trace ID
+            .layer(axum::middleware::from_fn(middleware::metrics_middleware))
+            .layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(DefaultMakeSpan::new().level(tracing::Level::INFO)),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                self.state.clone(),
+                middleware::security_headers,
+            ))
+            .with_state(self.state))
+    }
+}
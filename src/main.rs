@@ -1,99 +1,93 @@
-use axum::{
-    routing::{get, post},
-    Router,
-};
+use learn_rust::config::{Config, ConfigLoader, ConfigSources, LogFormat};
+use learn_rust::reload::ConfigReloader;
+use learn_rust::telemetry::{self, TracerStatus};
+use learn_rust::{runtime, shutdown, sinks, system, AppBuilder, AppState};
 use std::sync::Arc;
-use tower_http::cors::{Any, CorsLayer};
-use tower_http::trace::TraceLayer;
 use tracing::info;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-
-mod handlers;
-mod metrics;
-mod middleware;
-mod models;
-mod openapi;
-mod telemetry;
-
-#[cfg(test)]
-mod tests;
-
-use handlers::*;
-use models::AppState;
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter};
+
+fn main() {
+    // Load and validate configuration before anything else, the runtime
+    // itself is configured by it
+    let loader = ConfigLoader::from_env();
+    let (config, sources) = match loader.load_with_sources() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Configuration error: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-/// OpenAPI specification handler
-async fn openapi_handler() -> impl axum::response::IntoResponse {
-    use axum::http::StatusCode;
-    (
-        StatusCode::OK,
-        [("content-type", "application/json")],
-        openapi::get_openapi_json(),
-    )
+    let runtime = match runtime::build(&config.runtime) {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Failed to start the async runtime: {}", e);
+            std::process::exit(1);
+        }
+    };
+    runtime.block_on(run(loader, config, sources));
 }
 
-#[tokio::main]
-async fn main() {
-    // Initialize tracing
+async fn run(loader: ConfigLoader, config: Config, sources: ConfigSources) {
+    // Set up OpenTelemetry first so request spans are exported by the
+    // tracing subscriber
+    let tracer = telemetry::init_tracer();
+
+    // Initialize tracing, keeping a handle so the filter can be reloaded
+    let json_logs = config.log.format == LogFormat::Json;
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::new(&config.log.filter));
     tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "learn_rust=debug,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
+        .with(filter)
+        .with(telemetry::layer())
+        .with((!json_logs).then(tracing_subscriber::fmt::layer))
+        .with(json_logs.then(|| tracing_subscriber::fmt::layer().json()))
         .init();
 
-    // Initialize OpenTelemetry tracer
-    if let Err(e) = telemetry::init_tracer() {
-        tracing::warn!("[WARN] Failed to initialize OpenTelemetry tracer: {}", e);
+    match tracer {
+        Ok(TracerStatus::Exporting(endpoint)) => {
+            info!("[INFO] OpenTelemetry: Exporting traces to {}", endpoint)
+        }
+        Ok(TracerStatus::NotConfigured) => info!(
+            "[INFO] OpenTelemetry: OTEL_EXPORTER_OTLP_ENDPOINT not set, skipping OTLP configuration"
+        ),
+        Ok(TracerStatus::Disabled) => info!("[INFO] OpenTelemetry: Telemetry feature not enabled"),
+        Err(e) => tracing::warn!("[WARN] Failed to initialize OpenTelemetry tracer: {}", e),
     }
 
-    // Initialize Prometheus metrics
-    metrics::init_metrics();
-    info!("[INFO] Prometheus metrics initialized");
-
-    // Get configuration from environment
-    let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
-    let host = std::env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
-    let version = std::env::var("APP_VERSION").unwrap_or_else(|_| "0.0.1".to_string());
-    let environment = std::env::var("RUST_ENV").unwrap_or_else(|_| "development".to_string());
+    // Build address
+    let addr = config.bind_addr();
+    info!("🚀 Server starting at http://{}/", addr);
+    info!("📊 Environment: {}", config.app.environment);
+    info!("📦 Version: {}", config.app.version);
+    info!("🕐 Started at: {}", chrono::Utc::now().to_rfc3339());
 
-    // Create application state
-    let state = Arc::new(AppState::new(version, environment));
+    // Build application routes
+    let builder =
+        AppBuilder::with_state(Arc::new(AppState::with_sources(config, sources))).defer_startup();
+    let state = builder.state();
+    let app = match builder.build() {
+        Ok(app) => app,
+        Err(e) => {
+            tracing::error!("[ERROR] Metrics: {}", e);
+            std::process::exit(1);
+        }
+    };
+    info!("[INFO] Prometheus metrics initialized");
 
-    // Build CORS layer
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+    // Sample host metrics in the background instead of per request
+    system::spawn_sampler(state.clone());
 
-    // Build application routes
-    let app = Router::new()
-        .route("/", get(index))
-        .route("/ping", get(ping))
-        .route("/healthz", get(healthz))
-        .route("/info", get(info))
-        .route("/version", get(version_handler))
-        .route("/echo", post(echo))
-        .route("/metrics", get(metrics::metrics_handler))
-        .route("/openapi.json", get(openapi_handler))
-        .layer(cors)
-        .layer(TraceLayer::new_for_http())
-        .layer(axum::middleware::from_fn(middleware::security_headers))
-        .layer(axum::middleware::from_fn(middleware::metrics_middleware))
-        .with_state(state);
+    // Push metrics to the configured sinks and the OTLP collector, if any
+    let metrics_export = sinks::MetricsExport::start(&state);
 
-    // Build address
-    let addr = format!("{}:{}", host, port);
-    info!("🚀 Server starting at http://{}/", addr);
-    info!(
-        "📊 Environment: {}",
-        std::env::var("RUST_ENV").unwrap_or_else(|_| "development".to_string())
-    );
-    info!(
-        "📦 Version: {}",
-        std::env::var("APP_VERSION").unwrap_or_else(|_| "0.0.1".to_string())
-    );
-    info!("🕐 Started at: {}", chrono::Utc::now().to_rfc3339());
+    // Reload configuration on SIGHUP and config file changes
+    ConfigReloader::new(state.clone(), loader)
+        .on_reload(move |config| {
+            if let Err(e) = filter_handle.reload(EnvFilter::new(&config.log.filter)) {
+                tracing::error!("[ERROR] Config: failed to apply log filter: {}", e);
+            }
+        })
+        .spawn();
 
     // Create listener
     let listener = tokio::net::TcpListener::bind(&addr)
@@ -101,27 +95,15 @@ async fn main() {
         .unwrap_or_else(|e| panic!("Failed to bind to {}: {}", addr, e));
 
     info!("Listening on {}", addr);
+    state.mark_started();
 
-    // Start server with graceful shutdown
-    let server = axum::serve(listener, app);
-
-    // Handle graceful shutdown
-    let shutdown_signal = async {
-        tokio::signal::ctrl_c()
-            .await
-            .expect("failed to install Ctrl+C signal handler");
-        info!("Shutting down gracefully...");
-        telemetry::shutdown_tracer();
-    };
-
-    tokio::select! {
-        result = server => {
-            if let Err(e) = result {
-                tracing::error!("Server error: {}", e);
-            }
-        }
-        _ = shutdown_signal => {
-            info!("Shutdown signal received");
-        }
+    // Serve until SIGTERM/SIGINT, then drain in-flight requests
+    match shutdown::serve(listener, app, state, shutdown::signal()).await {
+        Ok(outcome) => info!("Server stopped: {:?}", outcome),
+        Err(e) => tracing::error!("Server error: {}", e),
     }
+
+    // Flush metrics and traces only once the last request has completed
+    metrics_export.shutdown().await;
+    telemetry::shutdown_tracer();
 }
@@ -1,46 +1,680 @@
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::{
+    extract::State,
+    http::{HeaderMap, Method, StatusCode},
+    response::IntoResponse,
+};
 use lazy_static::lazy_static;
-use prometheus::{Counter, Encoder, Histogram, HistogramOpts, Opts, Registry, TextEncoder};
-use std::sync::Once;
+use prometheus::core::Collector;
+use prometheus::{
+    Counter, CounterVec, Encoder, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec,
+    IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
 
-static INIT: Once = Once::new();
+use crate::config::{validate_buckets, BucketsConfig, MetricsConfig};
+use crate::handlers::accepts;
+use crate::models::{AppInfo, AppState, BUILD_DATE, COMMIT, RUSTC_VERSION};
+use crate::openmetrics::{self, Exemplars, OPENMETRICS_CONTENT_TYPE, OPENMETRICS_TEXT};
+use crate::runtime::RuntimeCollector;
+use crate::system::SystemSnapshot;
+
+/// Outcome of the first [`init_metrics`] call
+static INIT: OnceLock<Result<(), MetricsError>> = OnceLock::new();
+
+/// Naming, labels and buckets, fixed by the first [`init_metrics`] call
+static OPTIONS: OnceLock<MetricsOptions> = OnceLock::new();
+
+/// Route label of requests that matched no route, keeping cardinality bounded
+pub const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Labels of the HTTP request metrics
+const HTTP_LABELS: &[&str] = &["method", "route", "status"];
 
 lazy_static! {
-    pub static ref REGISTRY: Registry = Registry::new();
-    pub static ref HTTP_REQUESTS_TOTAL: Counter = Counter::with_opts(Opts::new(
-        "http_requests_total",
-        "Total number of HTTP requests"
-    ))
-    .expect("metric can be created");
-    pub static ref HTTP_REQUEST_DURATION_SECONDS: Histogram =
-        Histogram::with_opts(HistogramOpts::new(
+    /// Metrics added through [`counter`], [`gauge`] and [`histogram`]
+    static ref CUSTOM_METRICS: Mutex<CustomMetrics> = Mutex::new(CustomMetrics::default());
+    /// Trace exemplars of `http_request_duration_seconds`
+    pub static ref EXEMPLARS: Exemplars = Exemplars::new();
+    pub static ref REGISTRY: Registry = options().registry();
+    pub static ref HTTP_REQUESTS_TOTAL: CounterVec = CounterVec::new(
+        Opts::new("http_requests_total", "Total number of HTTP requests"),
+        HTTP_LABELS
+    )
+    .expect("metric can be created");
+    pub static ref HTTP_REQUEST_DURATION_SECONDS: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
             "http_request_duration_seconds",
             "HTTP request duration in seconds"
-        ))
-        .expect("metric can be created");
+        )
+        .buckets(options().buckets.http_request_duration_seconds.clone()),
+        HTTP_LABELS
+    )
+    .expect("metric can be created");
+    pub static ref HTTP_REQUESTS_IN_FLIGHT: IntGauge = IntGauge::new(
+        "http_requests_in_flight",
+        "Requests being handled, until their response body has been sent"
+    )
+    .expect("metric can be created");
+    pub static ref HTTP_REQUEST_SIZE_BYTES: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "http_request_size_bytes",
+            "Bytes of request body read by the handler"
+        )
+        .buckets(options().buckets.http_request_size_bytes.clone()),
+        &["method", "route"]
+    )
+    .expect("metric can be created");
+    pub static ref HTTP_RESPONSE_SIZE_BYTES: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "http_response_size_bytes",
+            "Bytes of response body sent for completed responses"
+        )
+        .buckets(options().buckets.http_response_size_bytes.clone()),
+        HTTP_LABELS
+    )
+    .expect("metric can be created");
+    pub static ref HTTP_REQUESTS_ABORTED_TOTAL: CounterVec = CounterVec::new(
+        Opts::new(
+            "http_requests_aborted_total",
+            "Requests cancelled before completing, by the phase they were in: request before a response was produced, response while sending the body"
+        ),
+        &["method", "route", "phase"]
+    )
+    .expect("metric can be created");
+    pub static ref HEALTH_RESOURCE_VALUE: GaugeVec = GaugeVec::new(
+        Opts::new(
+            "health_resource_value",
+            "Resource value compared against its health threshold: memory percent, load per CPU, open file descriptors, event loop lag in ms"
+        ),
+        &["resource"]
+    )
+    .expect("metric can be created");
+    pub static ref HEALTH_RESOURCE_LEVEL: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "health_resource_level",
+            "Resource health level: 0 ok, 1 degraded, 2 unhealthy"
+        ),
+        &["resource"]
+    )
+    .expect("metric can be created");
+    pub static ref SYSTEM_MEMORY_TOTAL_BYTES: Gauge = Gauge::new(
+        "system_memory_total_bytes",
+        "Memory limit of the container, or host memory when there is none"
+    )
+    .expect("metric can be created");
+    pub static ref SYSTEM_MEMORY_AVAILABLE_BYTES: Gauge = Gauge::new(
+        "system_memory_available_bytes",
+        "Memory still available below the total"
+    )
+    .expect("metric can be created");
+    pub static ref SYSTEM_MEMORY_USED_BYTES: Gauge = Gauge::new(
+        "system_memory_used_bytes",
+        "Memory in use, the working set when limited by a container"
+    )
+    .expect("metric can be created");
+    pub static ref SYSTEM_MEMORY_USED_PERCENT: Gauge = Gauge::new(
+        "system_memory_used_percent",
+        "Memory in use as a percentage of the total"
+    )
+    .expect("metric can be created");
+    pub static ref SYSTEM_CPU_COUNT: IntGauge =
+        IntGauge::new("system_cpu_count", "Logical CPUs of the host")
+            .expect("metric can be created");
+    pub static ref SYSTEM_LOAD_AVERAGE: Gauge = Gauge::new(
+        "system_load_average",
+        "One minute load average of the host"
+    )
+    .expect("metric can be created");
+    pub static ref SYSTEM_CPU_LOAD: Gauge = Gauge::new(
+        "system_cpu_load",
+        "One minute load average per CPU available to the service"
+    )
+    .expect("metric can be created");
+    pub static ref UPTIME_SECONDS: Gauge =
+        Gauge::new("uptime_seconds", "Seconds since the service started")
+            .expect("metric can be created");
 }
 
-pub fn init_metrics() {
-    INIT.call_once(|| {
-        REGISTRY
-            .register(Box::new(HTTP_REQUESTS_TOTAL.clone()))
-            .expect("collector can be registered");
+/// Metric options resolved from the configuration
+#[derive(Debug, PartialEq)]
+pub(crate) struct MetricsOptions {
+    pub(crate) prefix: Option<String>,
+    pub(crate) labels: HashMap<String, String>,
+    pub(crate) buckets: BucketsConfig,
+}
 
-        REGISTRY
-            .register(Box::new(HTTP_REQUEST_DURATION_SECONDS.clone()))
-            .expect("collector can be registered");
+impl MetricsOptions {
+    pub(crate) fn new(config: &MetricsConfig, app_info: Option<&AppInfo>) -> Self {
+        let prefix = [config.namespace.as_str(), config.subsystem.as_str()]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("_");
+
+        let mut labels: HashMap<_, _> = config.labels.clone().into_iter().collect();
+        if let (true, Some(app_info)) = (config.app_labels, app_info) {
+            labels.insert("service".to_string(), app_info.name.clone());
+            labels.insert("environment".to_string(), app_info.environment.clone());
+            labels.insert("version".to_string(), app_info.version.clone());
+        }
+
+        Self {
+            prefix: (!prefix.is_empty()).then_some(prefix),
+            labels,
+            buckets: config.buckets.clone(),
+        }
+    }
+
+    /// Registry adding the prefix and constant labels to every metric
+    pub(crate) fn registry(&self) -> Registry {
+        Registry::new_custom(self.prefix.clone(), Some(self.labels.clone()))
+            .expect("metrics options are validated")
+    }
+}
+
+fn options() -> &'static MetricsOptions {
+    OPTIONS.get_or_init(|| MetricsOptions::new(&MetricsConfig::default(), None))
+}
+
+/// Register the metrics, named and labelled following `config`
+///
+/// Metrics are process wide, so only the first call takes effect and later
+/// calls return its outcome. Fails when a custom metric created before this
+/// call clashes with a built-in one.
+pub fn init_metrics(config: &MetricsConfig, app_info: &AppInfo) -> Result<(), MetricsError> {
+    INIT.get_or_init(|| register_metrics(config, app_info))
+        .clone()
+}
 
-        // Register process metrics
-        let process_collector = prometheus::process_collector::ProcessCollector::for_self();
+fn register_metrics(config: &MetricsConfig, app_info: &AppInfo) -> Result<(), MetricsError> {
+    // Metrics touched before now were created with the default options,
+    // which can't be changed any more
+    let configured = MetricsOptions::new(config, Some(app_info));
+    if let Err(configured) = OPTIONS.set(configured) {
+        if *options() != configured {
+            tracing::error!(
+                "[ERROR] Metrics: metrics were recorded before being initialized, the configured namespace, labels and buckets are ignored"
+            );
+        }
+    }
+
+    let mut collectors: Vec<Box<dyn Collector>> = vec![
+        Box::new(HTTP_REQUESTS_TOTAL.clone()),
+        Box::new(HTTP_REQUEST_DURATION_SECONDS.clone()),
+        Box::new(HTTP_REQUESTS_IN_FLIGHT.clone()),
+        Box::new(HTTP_REQUEST_SIZE_BYTES.clone()),
+        Box::new(HTTP_RESPONSE_SIZE_BYTES.clone()),
+        Box::new(HTTP_REQUESTS_ABORTED_TOTAL.clone()),
+        Box::new(HEALTH_RESOURCE_VALUE.clone()),
+        Box::new(HEALTH_RESOURCE_LEVEL.clone()),
+        Box::new(SYSTEM_MEMORY_TOTAL_BYTES.clone()),
+        Box::new(SYSTEM_MEMORY_AVAILABLE_BYTES.clone()),
+        Box::new(SYSTEM_MEMORY_USED_BYTES.clone()),
+        Box::new(SYSTEM_MEMORY_USED_PERCENT.clone()),
+        Box::new(SYSTEM_CPU_COUNT.clone()),
+        Box::new(SYSTEM_LOAD_AVERAGE.clone()),
+        Box::new(SYSTEM_CPU_LOAD.clone()),
+        Box::new(UPTIME_SECONDS.clone()),
+        Box::new(build_info(&app_info.version)),
+        // Process metrics
+        Box::new(prometheus::process_collector::ProcessCollector::for_self()),
+    ];
+    // Scheduler metrics of the runtime serving requests
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        collectors.push(Box::new(RuntimeCollector::new(handle)));
+    }
+    for collector in collectors {
+        let name = collector
+            .desc()
+            .first()
+            .map(|desc| desc.fq_name.clone())
+            .unwrap_or_default();
         REGISTRY
-            .register(Box::new(process_collector))
-            .expect("process collector can be registered");
-    });
+            .register(collector)
+            .map_err(|e| registration_error(&name, e))?;
+    }
+
+    // Custom metrics created before now go after the built-in ones, so
+    // those always win a name clash
+    let mut custom = CUSTOM_METRICS.lock().unwrap();
+    custom.initialized = true;
+    let mut failed = None;
+    custom
+        .metrics
+        .retain(|name, metric| match register_custom(name, metric) {
+            Ok(()) => true,
+            Err(e) => {
+                failed.get_or_insert(e);
+                false
+            }
+        });
+    failed.map_or(Ok(()), Err)
+}
+
+/// Constant `build_info` gauge describing the running binary
+///
+/// Named `learn_rust_build_info` unless a namespace already prefixes it. The
+/// version label is left to the application labels when those are enabled.
+fn build_info(version: &str) -> IntGaugeVec {
+    let name = match options().prefix {
+        Some(_) => "build_info",
+        None => "learn_rust_build_info",
+    };
+    let mut labels = vec![
+        ("commit", COMMIT),
+        ("build_date", BUILD_DATE),
+        ("rustc", RUSTC_VERSION),
+    ];
+    if !options().labels.contains_key("version") {
+        labels.insert(0, ("version", version));
+    }
+    let names: Vec<_> = labels.iter().map(|(name, _)| *name).collect();
+    let values: Vec<_> = labels.iter().map(|(_, value)| *value).collect();
+
+    let gauge = IntGaugeVec::new(Opts::new(name, "Build information, always 1"), &names)
+        .expect("metric can be created");
+    gauge.with_label_values(&values).set(1);
+    gauge
+}
+
+/// Method label, with non-standard methods collapsed into `OTHER`
+pub fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::PATCH => "PATCH",
+        Method::HEAD => "HEAD",
+        Method::OPTIONS => "OPTIONS",
+        Method::CONNECT => "CONNECT",
+        Method::TRACE => "TRACE",
+        _ => "OTHER",
+    }
+}
+
+/// Status class label such as `2xx`
+pub fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+/// Record a finished HTTP request
+///
+/// The duration keeps the trace ID of the current span as an exemplar.
+pub fn record_request(method: &str, route: &str, status: StatusCode, duration: f64) {
+    let labels = [method, route, status_class(status)];
+    HTTP_REQUESTS_TOTAL.with_label_values(&labels).inc();
+    HTTP_REQUEST_DURATION_SECONDS
+        .with_label_values(&labels)
+        .observe(duration);
+
+    if let Some(trace_id) = crate::telemetry::current_trace_id() {
+        let labels: Vec<_> = HTTP_LABELS.iter().copied().zip(labels).collect();
+        EXEMPLARS.record(
+            &full_name("http_request_duration_seconds"),
+            &labels,
+            &options().buckets.http_request_duration_seconds,
+            duration,
+            &trace_id,
+        );
+    }
+}
+
+/// Record the request body bytes read while handling a request
+pub fn record_request_size(method: &str, route: &str, bytes: u64) {
+    HTTP_REQUEST_SIZE_BYTES
+        .with_label_values(&[method, route])
+        .observe(bytes as f64);
+}
+
+/// Record the body size of a response that was sent completely
+pub fn record_response_size(method: &str, route: &str, status: StatusCode, bytes: u64) {
+    HTTP_RESPONSE_SIZE_BYTES
+        .with_label_values(&[method, route, status_class(status)])
+        .observe(bytes as f64);
+}
+
+/// Record a request cancelled in the `request` or `response` phase
+pub fn record_aborted(method: &str, route: &str, phase: &str) {
+    HTTP_REQUESTS_ABORTED_TOTAL
+        .with_label_values(&[method, route, phase])
+        .inc();
+}
+
+/// Record the value and level of a resource evaluated against its thresholds
+pub fn record_resource(resource: &str, value: Option<f64>, level: i64) {
+    if let Some(value) = value {
+        HEALTH_RESOURCE_VALUE
+            .with_label_values(&[resource])
+            .set(value);
+    }
+    HEALTH_RESOURCE_LEVEL
+        .with_label_values(&[resource])
+        .set(level);
+}
+
+/// Mirror the memory, CPU and uptime figures reported by `/healthz`
+pub fn record_system(snapshot: &SystemSnapshot, uptime: f64) {
+    let memory = snapshot.memory();
+    SYSTEM_MEMORY_TOTAL_BYTES.set(memory.total as f64);
+    SYSTEM_MEMORY_AVAILABLE_BYTES.set(memory.available as f64);
+    SYSTEM_MEMORY_USED_BYTES.set(memory.used as f64);
+    SYSTEM_MEMORY_USED_PERCENT.set(memory.percent);
+    SYSTEM_CPU_COUNT.set(snapshot.cpu_count as i64);
+    SYSTEM_LOAD_AVERAGE.set(snapshot.load_average);
+    SYSTEM_CPU_LOAD.set(snapshot.cpu_load());
+    UPTIME_SECONDS.set(uptime);
+}
+
+/// Name of a metric as exposed, after the namespace and subsystem prefix
+fn full_name(name: &str) -> String {
+    match &options().prefix {
+        Some(prefix) => format!("{}_{}", prefix, name),
+        None => name.to_string(),
+    }
+}
+
+/// Error returned by the custom metrics API
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetricsError {
+    /// The name is taken by a built-in metric, or by a custom metric of
+    /// another type or with other label names
+    Conflict { name: String, message: String },
+    /// The name, a label name, the help text or the buckets are not valid
+    Invalid { name: String, message: String },
 }
+
+impl MetricsError {
+    fn conflict(name: &str, message: impl Into<String>) -> Self {
+        MetricsError::Conflict {
+            name: name.to_string(),
+            message: message.into(),
+        }
+    }
+
+    fn invalid(name: &str, message: impl Into<String>) -> Self {
+        MetricsError::Invalid {
+            name: name.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for MetricsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetricsError::Conflict { name, message } => {
+                write!(
+                    f,
+                    "metric '{}' conflicts with an existing metric: {}",
+                    name, message
+                )
+            }
+            MetricsError::Invalid { name, message } => {
+                write!(f, "invalid metric '{}': {}", name, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MetricsError {}
+
+/// Custom metrics by name, with the help and buckets they are created with
+#[derive(Default)]
+struct CustomMetrics {
+    /// Set once [`init_metrics`] registered the built-in metrics, custom
+    /// metrics created before then are registered by it
+    initialized: bool,
+    metrics: HashMap<String, CustomMetric>,
+    descriptions: HashMap<String, Description>,
+}
+
+struct CustomMetric {
+    vec: CustomVec,
+    label_names: Vec<String>,
+}
+
+#[derive(Clone)]
+enum CustomVec {
+    Counter(CounterVec),
+    Gauge(GaugeVec),
+    Histogram(HistogramVec),
+}
+
+impl CustomVec {
+    fn kind(&self) -> &'static str {
+        match self {
+            CustomVec::Counter(_) => "counter",
+            CustomVec::Gauge(_) => "gauge",
+            CustomVec::Histogram(_) => "histogram",
+        }
+    }
+
+    fn collector(&self) -> Box<dyn Collector> {
+        match self {
+            CustomVec::Counter(vec) => Box::new(vec.clone()),
+            CustomVec::Gauge(vec) => Box::new(vec.clone()),
+            CustomVec::Histogram(vec) => Box::new(vec.clone()),
+        }
+    }
+}
+
+/// Help text and buckets set by [`describe`] or [`describe_histogram`]
+struct Description {
+    help: String,
+    buckets: Vec<f64>,
+}
+
+impl Default for Description {
+    fn default() -> Self {
+        Self {
+            help: "Custom application metric".to_string(),
+            buckets: prometheus::DEFAULT_BUCKETS.to_vec(),
+        }
+    }
+}
+
+/// Counter `name` with the given label values, registered on first use
+///
+/// Later calls with the same name must pass the same label names. The metric
+/// is served on `/metrics` and pushed to the sinks like the built-in ones.
+pub fn counter(name: &str, labels: &[(&str, &str)]) -> Result<Counter, MetricsError> {
+    let (vec, values) = custom_metric(name, labels, |description, label_names| {
+        CounterVec::new(Opts::new(name, &description.help), label_names).map(CustomVec::Counter)
+    })?;
+    match vec {
+        CustomVec::Counter(vec) => child(name, vec.get_metric_with_label_values(&values)),
+        other => Err(registered_as(name, &other)),
+    }
+}
+
+/// Gauge `name` with the given label values, registered on first use
+pub fn gauge(name: &str, labels: &[(&str, &str)]) -> Result<Gauge, MetricsError> {
+    let (vec, values) = custom_metric(name, labels, |description, label_names| {
+        GaugeVec::new(Opts::new(name, &description.help), label_names).map(CustomVec::Gauge)
+    })?;
+    match vec {
+        CustomVec::Gauge(vec) => child(name, vec.get_metric_with_label_values(&values)),
+        other => Err(registered_as(name, &other)),
+    }
+}
+
+/// Histogram `name` with the given label values, registered on first use
+///
+/// Uses the default Prometheus buckets unless [`describe_histogram`] set
+/// others.
+pub fn histogram(name: &str, labels: &[(&str, &str)]) -> Result<Histogram, MetricsError> {
+    let (vec, values) = custom_metric(name, labels, |description, label_names| {
+        HistogramVec::new(
+            HistogramOpts::new(name, &description.help).buckets(description.buckets.clone()),
+            label_names,
+        )
+        .map(CustomVec::Histogram)
+    })?;
+    match vec {
+        CustomVec::Histogram(vec) => child(name, vec.get_metric_with_label_values(&values)),
+        other => Err(registered_as(name, &other)),
+    }
+}
+
+/// Set the help text of a custom metric, before its first use
+pub fn describe(name: &str, help: &str) -> Result<(), MetricsError> {
+    set_description(
+        name,
+        Description {
+            help: help.to_string(),
+            ..Description::default()
+        },
+    )
+}
+
+/// Set the help text and bucket bounds of a custom histogram, before its
+/// first use
+pub fn describe_histogram(name: &str, help: &str, buckets: &[f64]) -> Result<(), MetricsError> {
+    validate_buckets(buckets).map_err(|e| MetricsError::invalid(name, e))?;
+    set_description(
+        name,
+        Description {
+            help: help.to_string(),
+            buckets: buckets.to_vec(),
+        },
+    )
+}
+
+fn set_description(name: &str, description: Description) -> Result<(), MetricsError> {
+    if description.help.trim().is_empty() {
+        return Err(MetricsError::invalid(name, "help must not be empty"));
+    }
+    let mut custom = CUSTOM_METRICS.lock().unwrap();
+    if custom.metrics.contains_key(name) {
+        return Err(MetricsError::conflict(
+            name,
+            "already in use, describe it before its first use",
+        ));
+    }
+    custom.descriptions.insert(name.to_string(), description);
+    Ok(())
+}
+
+/// Look up custom metric `name`, creating it with `create` on first use
+///
+/// Returns the metric with `labels` values ordered like its label names.
+fn custom_metric<'a>(
+    name: &str,
+    labels: &[(&str, &'a str)],
+    create: impl FnOnce(&Description, &[&str]) -> prometheus::Result<CustomVec>,
+) -> Result<(CustomVec, Vec<&'a str>), MetricsError> {
+    let mut custom = CUSTOM_METRICS.lock().unwrap();
+    if !custom.metrics.contains_key(name) {
+        let label_names: Vec<&str> = labels.iter().map(|(label, _)| *label).collect();
+        let vec = create(
+            custom
+                .descriptions
+                .get(name)
+                .unwrap_or(&Description::default()),
+            &label_names,
+        )
+        .map_err(|e| MetricsError::invalid(name, e.to_string()))?;
+        let metric = CustomMetric {
+            vec,
+            label_names: label_names.iter().map(|label| label.to_string()).collect(),
+        };
+        // Until the built-in metrics are registered, registration is left to
+        // `init_metrics`
+        if custom.initialized {
+            register_custom(name, &metric)?;
+        }
+        custom.metrics.insert(name.to_string(), metric);
+    }
+
+    let metric = &custom.metrics[name];
+    let values: Option<Vec<&str>> = metric
+        .label_names
+        .iter()
+        .map(|label| {
+            labels
+                .iter()
+                .find(|(name, _)| name == label)
+                .map(|(_, value)| *value)
+        })
+        .collect();
+    match values {
+        Some(values) if labels.len() == metric.label_names.len() => {
+            Ok((metric.vec.clone(), values))
+        }
+        _ => Err(MetricsError::conflict(
+            name,
+            format!("registered with labels {:?}", metric.label_names),
+        )),
+    }
+}
+
+/// Add a custom metric to [`REGISTRY`]
+fn register_custom(name: &str, metric: &CustomMetric) -> Result<(), MetricsError> {
+    // Constant labels are added to every series, so a metric can't set them
+    if let Some(label) = metric
+        .label_names
+        .iter()
+        .find(|label| options().labels.contains_key(*label))
+    {
+        return Err(MetricsError::invalid(
+            name,
+            format!("label '{}' is added to every metric already", label),
+        ));
+    }
+    REGISTRY
+        .register(metric.vec.collector())
+        .map_err(|e| registration_error(name, e))
+}
+
+fn registration_error(name: &str, error: prometheus::Error) -> MetricsError {
+    match error {
+        prometheus::Error::AlreadyReg => {
+            MetricsError::conflict(name, "a metric with this name is already registered")
+        }
+        e => MetricsError::conflict(name, e.to_string()),
+    }
+}
+
+fn child<T>(name: &str, metric: prometheus::Result<T>) -> Result<T, MetricsError> {
+    metric.map_err(|e| MetricsError::invalid(name, e.to_string()))
+}
+
+fn registered_as(name: &str, vec: &CustomVec) -> MetricsError {
+    MetricsError::conflict(name, format!("registered as a {}", vec.kind()))
+}
+
 /// Metrics endpoint handler
-pub async fn metrics_handler() -> impl IntoResponse {
-    let encoder = TextEncoder::new();
+///
+/// Serves the OpenMetrics format, with trace exemplars, to scrapers that
+/// accept `application/openmetrics-text` and the Prometheus text format
+/// otherwise.
+pub async fn metrics_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    // System gauges follow the cached snapshot, so scrapes stay cheap
+    record_system(&state.system.snapshot(), state.uptime());
+
     let metric_families = REGISTRY.gather();
+
+    if accepts(&headers, OPENMETRICS_TEXT) {
+        return (
+            StatusCode::OK,
+            [("content-type", OPENMETRICS_CONTENT_TYPE)],
+            openmetrics::encode(&metric_families, &EXEMPLARS).into_bytes(),
+        );
+    }
+
+    let encoder = TextEncoder::new();
     let mut buffer = vec![];
 
     match encoder.encode(&metric_families, &mut buffer) {
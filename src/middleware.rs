@@ -1,47 +1,380 @@
-use axum::{extract::Request, http::header, middleware::Next, response::Response};
-use std::time::Instant;
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use http_body::{Frame, SizeHint};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, ServiceExt};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+
+use crate::config::CorsConfig;
+use crate::models::{ApiResponse, AppState};
 
 /// Security headers middleware
-pub async fn security_headers(request: Request, next: Next) -> Response {
+pub async fn security_headers(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
     let mut response = next.run(request).await;
 
+    let config = &state.config().security_headers;
+    if !config.enabled {
+        return response;
+    }
+
     let headers = response.headers_mut();
     headers.insert(
         header::HeaderName::from_static("x-content-type-options"),
         header::HeaderValue::from_static("nosniff"),
     );
-    headers.insert(
-        header::HeaderName::from_static("x-frame-options"),
-        header::HeaderValue::from_static("DENY"),
-    );
     headers.insert(
         header::HeaderName::from_static("x-xss-protection"),
         header::HeaderValue::from_static("1; mode=block"),
     );
-    headers.insert(
-        header::HeaderName::from_static("referrer-policy"),
-        header::HeaderValue::from_static("strict-origin-when-cross-origin"),
-    );
-    headers.insert(
-        header::HeaderName::from_static("content-security-policy"),
-        header::HeaderValue::from_static("default-src 'self'"),
-    );
+    for (name, value) in [
+        ("x-frame-options", &config.frame_options),
+        ("referrer-policy", &config.referrer_policy),
+        ("content-security-policy", &config.content_security_policy),
+    ] {
+        if let Ok(value) = HeaderValue::from_str(value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+    if config.hsts_max_age > 0 {
+        let value = format!("max-age={}; includeSubDomains", config.hsts_max_age);
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(header::STRICT_TRANSPORT_SECURITY, value);
+        }
+    }
 
     response
 }
 
-/// Metrics middleware - tracks request counts and duration
+/// CORS middleware following the active configuration, so reloads apply
+/// without rebuilding the router
+pub async fn cors(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    match state.cors_layer().layer(next).oneshot(request).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    }
+}
+
+/// Require the admin bearer token, hiding admin endpoints when none is set
+pub async fn admin_auth(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let token = state.config().admin.token.clone();
+    if token.is_empty() {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::<()>::error(
+                "admin endpoints are disabled".to_string(),
+            )),
+        )
+            .into_response();
+    }
+
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+
+    if !constant_time_eq(provided.as_bytes(), token.as_bytes()) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            Json(ApiResponse::<()>::error("unauthorized".to_string())),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Compare secrets without leaking the position of the first mismatch
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Global token bucket rate limiting, skipping exempt paths
+pub async fn rate_limit(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = &state.config().rate_limit;
+    let exempt = config
+        .exempt_paths
+        .iter()
+        .any(|path| path == request.uri().path());
+
+    if !exempt
+        && !state
+            .rate_limiter
+            .try_acquire(config.requests_per_second, config.burst)
+    {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(ApiResponse::<()>::error("rate limit exceeded".to_string())),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Token bucket shared by every request
+#[derive(Debug)]
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                tokens: f64::INFINITY,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take a token, refilling at `rate` per second up to `burst` tokens
+    ///
+    /// A rate of 0 disables limiting and a burst of 0 uses the rate.
+    pub fn try_acquire(&self, rate: u64, burst: u64) -> bool {
+        if rate == 0 {
+            return true;
+        }
+        let capacity = if burst == 0 { rate } else { burst } as f64;
+
+        // The bucket is consistent after every statement, so a panic while
+        // holding the lock can't leave it broken
+        let mut bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * rate as f64;
+        bucket.tokens = (bucket.tokens + refill).min(capacity);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Build the CORS layer from configuration
+pub fn cors_layer(config: &CorsConfig) -> CorsLayer {
+    let wildcard = |values: &[String]| values.iter().any(|v| v == "*");
+
+    let origins = if wildcard(&config.allowed_origins) {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            config
+                .allowed_origins
+                .iter()
+                .filter_map(|v| HeaderValue::from_str(v).ok()),
+        )
+    };
+    let methods = if wildcard(&config.allowed_methods) {
+        AllowMethods::any()
+    } else {
+        AllowMethods::list(
+            config
+                .allowed_methods
+                .iter()
+                .filter_map(|v| Method::from_bytes(v.as_bytes()).ok()),
+        )
+    };
+    let headers = if wildcard(&config.allowed_headers) {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(
+            config
+                .allowed_headers
+                .iter()
+                .filter_map(|v| HeaderName::from_bytes(v.as_bytes()).ok()),
+        )
+    };
+
+    let mut cors = CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(headers);
+    if config.max_age > 0 {
+        cors = cors.max_age(Duration::from_secs(config.max_age));
+    }
+    cors
+}
+
+/// Metrics middleware - tracks request counts, duration, body sizes and
+/// requests in flight
+///
+/// A request stays in flight until its response body has been sent, so
+/// clients that read or write slowly show up in the gauge. Dropping the
+/// request future, or the response body with data left to send, counts the
+/// request as aborted. Responses to `HEAD` never send their body.
 pub async fn metrics_middleware(request: Request, next: Next) -> Response {
     let start = Instant::now();
+    let method = crate::metrics::method_label(request.method());
+    let head = request.method() == Method::HEAD;
+    let route: Arc<str> = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str())
+        .unwrap_or(crate::metrics::UNMATCHED_ROUTE)
+        .into();
+    let mut in_flight = InFlight::new(method, route.clone());
 
-    // Increment request counter
-    crate::metrics::HTTP_REQUESTS_TOTAL.inc();
+    let request = {
+        let route = route.clone();
+        request.map(|body| {
+            MeteredBody::wrap(body, move |bytes, _| {
+                crate::metrics::record_request_size(method, &route, bytes)
+            })
+        })
+    };
 
     let response = next.run(request).await;
+    let status = response.status();
 
-    // Record request duration
-    let duration = start.elapsed();
-    crate::metrics::HTTP_REQUEST_DURATION_SECONDS.observe(duration.as_secs_f64());
+    // Record request count and duration by route, method and status class
+    crate::metrics::record_request(method, &route, status, start.elapsed().as_secs_f64());
 
-    response
+    in_flight.responded = true;
+    response.map(|body| {
+        MeteredBody::wrap(body, move |bytes, complete| {
+            if complete || head {
+                crate::metrics::record_response_size(method, &route, status, bytes);
+            } else {
+                crate::metrics::record_aborted(method, &route, "response");
+            }
+            drop(in_flight);
+        })
+    })
+}
+
+/// Counts a request in flight until dropped, recording it as aborted when
+/// dropped before a response was produced
+struct InFlight {
+    method: &'static str,
+    route: Arc<str>,
+    responded: bool,
+}
+
+impl InFlight {
+    fn new(method: &'static str, route: Arc<str>) -> Self {
+        crate::metrics::HTTP_REQUESTS_IN_FLIGHT.inc();
+        Self {
+            method,
+            route,
+            responded: false,
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if !self.responded {
+            crate::metrics::record_aborted(self.method, &self.route, "request");
+        }
+        crate::metrics::HTTP_REQUESTS_IN_FLIGHT.dec();
+    }
+}
+
+type OnFinish = Box<dyn FnOnce(u64, bool) + Send>;
+
+/// Body counting the data bytes that pass through it
+///
+/// `on_finish` runs once with the byte count and whether the body reached
+/// its end, either when the last frame is read or when the body is dropped.
+/// A body dropped with no data left counts as complete.
+struct MeteredBody {
+    inner: Body,
+    bytes: u64,
+    on_finish: Option<OnFinish>,
+}
+
+impl MeteredBody {
+    fn wrap(inner: Body, on_finish: impl FnOnce(u64, bool) + Send + 'static) -> Body {
+        Body::new(Self {
+            inner,
+            bytes: 0,
+            on_finish: Some(Box::new(on_finish)),
+        })
+    }
+
+    fn finish(&mut self, complete: bool) {
+        if let Some(on_finish) = self.on_finish.take() {
+            on_finish(self.bytes, complete);
+        }
+    }
+}
+
+impl HttpBody for MeteredBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.bytes += data.len() as u64;
+                }
+                if self.inner.is_end_stream() {
+                    self.finish(true);
+                }
+            }
+            Poll::Ready(Some(Err(_))) => self.finish(false),
+            Poll::Ready(None) => self.finish(true),
+            Poll::Pending => {}
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for MeteredBody {
+    fn drop(&mut self) {
+        let complete = self.inner.is_end_stream() || self.inner.size_hint().upper() == Some(0);
+        self.finish(complete);
+    }
 }
@@ -1,25 +1,166 @@
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 use std::time::SystemTime;
-use utoipa::ToSchema;
+use tower_http::cors::CorsLayer;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::cgroup::CgroupStats;
+use crate::config::{Config, ConfigSources};
+use crate::health::{
+    CheckReport, CheckStatus, HealthRegistry, HealthReport, HealthStatus, Lifecycle, Phase,
+};
+use crate::middleware::{cors_layer, RateLimiter};
+use crate::platform::RuntimeInfo;
+use crate::resources::ResourceMonitor;
+use crate::system::SystemSampler;
 
 /// Application state shared across handlers
 pub struct AppState {
     pub app_info: AppInfo,
     pub start_time: SystemTime,
+    pub rate_limiter: RateLimiter,
+    pub health: HealthRegistry,
+    pub system: SystemSampler,
+    pub resources: Arc<ResourceMonitor>,
+    /// Platform detected at startup
+    pub runtime: RuntimeInfo,
+    config: RwLock<ActiveConfig>,
+    config_generation: AtomicU64,
+    lifecycle: Lifecycle,
+}
+
+/// Configuration currently in use, swapped as a whole on reload
+struct ActiveConfig {
+    config: Arc<Config>,
+    sources: Arc<ConfigSources>,
+    /// CORS layer built from `config`, so requests don't parse it again
+    cors: CorsLayer,
+}
+
+impl ActiveConfig {
+    fn new(config: Config, sources: ConfigSources) -> Self {
+        Self {
+            cors: cors_layer(&config.cors),
+            config: Arc::new(config),
+            sources: Arc::new(sources),
+        }
+    }
 }
 
 impl AppState {
-    pub fn new(version: String, environment: String) -> Self {
+    pub fn new(config: Config) -> Self {
+        Self::with_sources(config, ConfigSources::default())
+    }
+
+    /// Create state remembering where each configuration value came from
+    pub fn with_sources(config: Config, sources: ConfigSources) -> Self {
         Self {
             app_info: AppInfo {
                 name: "learn-rust".to_string(),
-                version,
-                environment,
+                version: config.app.version.clone(),
+                environment: config.app.environment.clone(),
                 timestamp: Utc::now().to_rfc3339(),
             },
             start_time: SystemTime::now(),
+            rate_limiter: RateLimiter::new(),
+            health: HealthRegistry::new(),
+            system: SystemSampler::new(),
+            resources: Arc::new(ResourceMonitor::new()),
+            runtime: RuntimeInfo::detect(),
+            config: RwLock::new(ActiveConfig::new(config, sources)),
+            config_generation: AtomicU64::new(1),
+            lifecycle: Lifecycle::new(),
+        }
+    }
+
+    /// Seconds since the state was created
+    pub fn uptime(&self) -> f64 {
+        self.start_time
+            .elapsed()
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0)
+    }
+
+    /// Mark initialization as finished so startup and readiness probes pass
+    pub fn mark_started(&self) {
+        self.lifecycle.mark_started();
+    }
+
+    /// Whether initialization has finished
+    pub fn is_started(&self) -> bool {
+        self.lifecycle.phase() != Phase::Starting
+    }
+
+    /// Mark the service as shutting down so readiness starts failing
+    pub fn start_draining(&self) {
+        self.lifecycle.start_draining();
+    }
+
+    /// Whether a shutdown has started
+    pub fn is_draining(&self) -> bool {
+        self.lifecycle.phase() == Phase::Draining
+    }
+
+    /// Current health derived from the lifecycle phase
+    pub fn health_status(&self) -> HealthStatus {
+        match self.lifecycle.phase() {
+            Phase::Starting => HealthStatus::Starting,
+            Phase::Ready => HealthStatus::Ready,
+            Phase::Draining => HealthStatus::Draining,
+        }
+    }
+
+    /// Run the registered health checks
+    ///
+    /// Once started the aggregated check status is reported, while starting
+    /// or draining the lifecycle phase takes precedence.
+    pub async fn health_report(&self) -> HealthReport {
+        let mut report = self.health.run().await;
+        let phase = self.health_status();
+        if phase != HealthStatus::Ready {
+            report.status = phase;
         }
+        report
+    }
+
+    /// Snapshot of the active configuration
+    pub fn config(&self) -> Arc<Config> {
+        self.config
+            .read()
+            .expect("config lock poisoned")
+            .config
+            .clone()
+    }
+
+    /// Snapshot of the active configuration with the source of each value
+    pub fn config_with_sources(&self) -> (Arc<Config>, Arc<ConfigSources>) {
+        let active = self.config.read().expect("config lock poisoned");
+        (active.config.clone(), active.sources.clone())
+    }
+
+    /// CORS layer of the active configuration
+    pub fn cors_layer(&self) -> CorsLayer {
+        self.config
+            .read()
+            .expect("config lock poisoned")
+            .cors
+            .clone()
+    }
+
+    /// Generation of the active configuration, starting at 1 and bumped on
+    /// every reload
+    pub fn config_generation(&self) -> u64 {
+        self.config_generation.load(Ordering::SeqCst)
+    }
+
+    /// Atomically swap in a new configuration, returning its generation
+    pub fn replace_config(&self, config: Config, sources: ConfigSources) -> u64 {
+        let mut current = self.config.write().expect("config lock poisoned");
+        *current = ActiveConfig::new(config, sources);
+        self.config_generation.fetch_add(1, Ordering::SeqCst) + 1
     }
 }
 
@@ -95,13 +236,141 @@ pub struct Endpoint {
     pub description: String,
 }
 
+/// Liveness, readiness and startup probe data
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ProbeData {
+    pub status: HealthStatus,
+    pub uptime: f64,
+    /// Health check results, only reported by `/readyz`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<CheckReport>,
+}
+
+/// Query parameters accepted by `/readyz` and `/healthz`
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HealthQuery {
+    /// Return a plaintext `[+]check ok` report instead of JSON
+    pub verbose: Option<String>,
+}
+
+/// Health check response in the IETF `application/health+json` format
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthJson {
+    pub status: CheckStatus,
+    pub version: String,
+    pub service_id: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Observations keyed by `component:measurement`
+    pub checks: BTreeMap<String, Vec<HealthJsonCheck>>,
+}
+
+/// Single observation of a component in the health+json format
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthJsonCheck {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub component_type: Option<String>,
+    pub observed_value: f64,
+    pub observed_unit: String,
+    pub status: CheckStatus,
+    pub time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+}
+
+impl HealthJson {
+    /// Start a document for the given overall status and check results
+    pub fn new(app_info: &AppInfo, status: HealthStatus, checks: &[CheckReport]) -> Self {
+        let time = Utc::now().to_rfc3339();
+        let checks = checks
+            .iter()
+            .map(|check| {
+                (
+                    format!("{}:responseTime", check.name),
+                    vec![HealthJsonCheck {
+                        component_type: None,
+                        observed_value: check.duration_ms,
+                        observed_unit: "ms".to_string(),
+                        status: check.status,
+                        time: time.clone(),
+                        output: check.message.clone(),
+                    }],
+                )
+            })
+            .collect();
+        Self {
+            status: status.check_status(),
+            version: app_info.version.clone(),
+            service_id: app_info.name.clone(),
+            description: format!("health of {}", app_info.name),
+            output: (!status.is_ready()).then(|| {
+                serde_json::to_value(status)
+                    .ok()
+                    .and_then(|v| v.as_str().map(str::to_string))
+                    .unwrap_or_default()
+            }),
+            checks,
+        }
+    }
+
+    /// Add a system observation such as uptime or memory utilization
+    pub fn observe(mut self, key: &str, value: f64, unit: &str) -> Self {
+        self.checks.insert(
+            key.to_string(),
+            vec![HealthJsonCheck {
+                component_type: Some("system".to_string()),
+                observed_value: value,
+                observed_unit: unit.to_string(),
+                status: CheckStatus::Pass,
+                time: Utc::now().to_rfc3339(),
+                output: None,
+            }],
+        );
+        self
+    }
+}
+
 /// Health check data
+///
+/// Every section except `status` can be left out with `?include=` or
+/// `?fields=`.
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct HealthData {
-    pub status: String,
-    pub uptime: f64,
-    pub memory: MemoryInfo,
-    pub system: SystemInfo,
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uptime: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<SystemInfo>,
+    /// Result of every registered health check
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checks: Option<Vec<CheckReport>>,
+}
+
+impl HealthData {
+    /// Sections that can be selected
+    pub const SECTIONS: &'static [&'static str] = &["uptime", "memory", "system", "checks"];
+
+    /// The same data in the IETF `application/health+json` format
+    pub fn to_health_json(&self, app_info: &AppInfo) -> HealthJson {
+        let mut json = HealthJson::new(
+            app_info,
+            self.status,
+            self.checks.as_deref().unwrap_or_default(),
+        );
+        if let Some(uptime) = self.uptime {
+            json = json.observe("uptime", uptime, "s");
+        }
+        if let Some(memory) = &self.memory {
+            json = json.observe("memory:utilization", memory.percent, "%");
+        }
+        json
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -110,6 +379,16 @@ pub struct MemoryInfo {
     pub available: u64,
     pub used: u64,
     pub percent: f64,
+    /// Whether the figures are the container's limit or host memory
+    pub source: MemorySource,
+}
+
+/// Origin of the figures in [`MemoryInfo`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MemorySource {
+    Host,
+    Cgroup,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -118,14 +397,62 @@ pub struct SystemInfo {
     pub arch: String,
     pub cpu_count: usize,
     pub hostname: String,
+    /// Container limits and usage, absent when not running in a cgroup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cgroup: Option<CgroupStats>,
+    /// Seconds since memory and CPU figures were sampled
+    pub sample_age: f64,
 }
 
 /// System information data
+///
+/// Sections can be left out with `?include=` or `?fields=`.
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct InfoData {
-    pub application: AppInfo,
-    pub system: DetailedSystemInfo,
-    pub environment: EnvironmentInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub application: Option<AppInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<DetailedSystemInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process: Option<ProcessInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<RuntimeInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<EnvironmentInfo>,
+}
+
+impl InfoData {
+    /// Sections that can be selected
+    pub const SECTIONS: &'static [&'static str] =
+        &["application", "system", "process", "runtime", "environment"];
+}
+
+/// Resource usage of the service's own process
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    /// Resident set size in bytes
+    pub rss: u64,
+    /// Virtual memory in bytes
+    pub virtual_memory: u64,
+    /// CPU usage since the previous sample, 100 is one full core
+    pub cpu_percent: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_fds: Option<u64>,
+    /// RFC 3339 time the process started
+    pub start_time: String,
+    pub tokio: TokioRuntimeInfo,
+}
+
+/// Statistics of the tokio runtime serving the request
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TokioRuntimeInfo {
+    pub workers: usize,
+    pub alive_tasks: usize,
+    /// Tasks waiting in the global injection queue
+    pub global_queue_depth: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -136,6 +463,11 @@ pub struct DetailedSystemInfo {
     pub cpu_count: usize,
     pub uptime: f64,
     pub memory: MemoryInfo,
+    /// Container limits and usage, absent when not running in a cgroup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cgroup: Option<CgroupStats>,
+    /// Seconds since memory and CPU figures were sampled
+    pub sample_age: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -143,8 +475,24 @@ pub struct EnvironmentInfo {
     pub rust_version: String,
     pub port: String,
     pub host: String,
+    pub config_generation: u64,
 }
 
+/// Build date set through `BUILD_DATE` at compile time
+pub const BUILD_DATE: &str = match option_env!("BUILD_DATE") {
+    Some(date) => date,
+    None => "unknown",
+};
+
+/// Commit set through `VCS_REF` at compile time
+pub const COMMIT: &str = match option_env!("VCS_REF") {
+    Some(commit) => commit,
+    None => "unknown",
+};
+
+/// Version of the compiler that built the binary
+pub const RUSTC_VERSION: &str = env!("RUSTC_VERSION");
+
 /// Version information
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct VersionData {
@@ -164,3 +512,22 @@ pub struct EchoResponse {
     pub message: String,
     pub received_at: String,
 }
+
+/// Effective configuration, as reported by `/config`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ConfigData {
+    pub generation: u64,
+    pub values: Vec<ConfigValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ConfigValue {
+    pub key: String,
+    #[schema(value_type = Object)]
+    pub value: serde_json::Value,
+    /// Layer the value came from: default, file, env or flag
+    pub source: String,
+    /// File, environment variable or flag that set the value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+}
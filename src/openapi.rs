@@ -1,4 +1,5 @@
-use utoipa::OpenApi;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
 
 #[derive(OpenApi)]
 #[openapi(
@@ -14,19 +15,39 @@ use utoipa::OpenApi;
         crate::handlers::index,
         crate::handlers::ping,
         crate::handlers::healthz,
+        crate::handlers::livez,
+        crate::handlers::readyz,
+        crate::handlers::startupz,
         crate::handlers::info,
         crate::handlers::version_handler,
         crate::handlers::echo,
+        crate::handlers::config_handler,
     ),
     components(
         schemas(
             crate::models::ApiResponse<crate::models::WelcomeData>,
             crate::models::ApiResponse<crate::models::HealthData>,
+            crate::models::ApiResponse<crate::models::ProbeData>,
             crate::models::ApiResponse<crate::models::InfoData>,
             crate::models::ApiResponse<crate::models::VersionData>,
             crate::models::ApiResponse<crate::models::EchoResponse>,
+            crate::models::ApiResponse<crate::models::ConfigData>,
             crate::models::WelcomeData,
             crate::models::HealthData,
+            crate::models::ProbeData,
+            crate::health::HealthStatus,
+            crate::health::CheckStatus,
+            crate::health::CheckReport,
+            crate::models::MemorySource,
+            crate::models::ProcessInfo,
+            crate::models::TokioRuntimeInfo,
+            crate::platform::RuntimeInfo,
+            crate::platform::Platform,
+            crate::platform::KubernetesInfo,
+            crate::cgroup::CgroupStats,
+            crate::cgroup::CgroupVersion,
+            crate::models::HealthJson,
+            crate::models::HealthJsonCheck,
             crate::models::InfoData,
             crate::models::VersionData,
             crate::models::EchoRequest,
@@ -39,16 +60,43 @@ use utoipa::OpenApi;
             crate::models::SystemInfo,
             crate::models::DetailedSystemInfo,
             crate::models::EnvironmentInfo,
+            crate::models::ConfigData,
+            crate::models::ConfigValue,
         )
     ),
     tags(
         (name = "health", description = "Health check endpoints"),
         (name = "info", description = "Information endpoints"),
-        (name = "utility", description = "Utility endpoints")
-    )
+        (name = "utility", description = "Utility endpoints"),
+        (name = "admin", description = "Admin endpoints, require the admin bearer token")
+    ),
+    modifiers(&AdminTokenAddon)
 )]
 pub struct ApiDoc;
 
+/// Registers the bearer token scheme used by admin endpoints
+struct AdminTokenAddon;
+
+impl Modify for AdminTokenAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "admin_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
 pub fn get_openapi_json() -> String {
     ApiDoc::openapi().to_pretty_json().unwrap()
 }
+
+/// OpenAPI specification handler
+pub async fn openapi_handler() -> impl axum::response::IntoResponse {
+    use axum::http::StatusCode;
+    (
+        StatusCode::OK,
+        [("content-type", "application/json")],
+        get_openapi_json(),
+    )
+}
@@ -0,0 +1,265 @@
+use prometheus::proto::{LabelPair, Metric, MetricFamily, MetricType};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Media type of the OpenMetrics text exposition format
+pub const OPENMETRICS_TEXT: &str = "application/openmetrics-text";
+
+/// Content type of responses in the OpenMetrics text format
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// A sampled observation linking a histogram bucket to a trace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exemplar {
+    pub trace_id: String,
+    pub value: f64,
+    /// Seconds since the Unix epoch
+    pub timestamp: f64,
+}
+
+/// Latest exemplar of every histogram bucket, by family and series
+#[derive(Debug, Default)]
+pub struct Exemplars {
+    families: Mutex<HashMap<String, FamilyExemplars>>,
+}
+
+#[derive(Debug, Default)]
+struct FamilyExemplars {
+    label_names: Vec<String>,
+    /// Per label values, one slot per bucket plus `+Inf`
+    series: HashMap<Vec<String>, Vec<Option<Exemplar>>>,
+}
+
+impl Exemplars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep `value` as the exemplar of the bucket it falls into
+    pub fn record(
+        &self,
+        family: &str,
+        labels: &[(&str, &str)],
+        bounds: &[f64],
+        value: f64,
+        trace_id: &str,
+    ) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let bucket = bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(bounds.len());
+
+        let mut families = self.families.lock().unwrap();
+        let entry = families.entry(family.to_string()).or_default();
+        entry.label_names = labels.iter().map(|(name, _)| name.to_string()).collect();
+        let slots = entry
+            .series
+            .entry(labels.iter().map(|(_, value)| value.to_string()).collect())
+            .or_default();
+        slots.resize(bounds.len() + 1, None);
+        slots[bucket] = Some(Exemplar {
+            trace_id: trace_id.to_string(),
+            value,
+            timestamp,
+        });
+    }
+
+    /// Exemplars of a gathered histogram series, one slot per bucket plus `+Inf`
+    fn series(&self, family: &str, metric: &Metric) -> Vec<Option<Exemplar>> {
+        let families = self.families.lock().unwrap();
+        let Some(entry) = families.get(family) else {
+            return Vec::new();
+        };
+        let key: Option<Vec<String>> = entry
+            .label_names
+            .iter()
+            .map(|name| {
+                metric
+                    .get_label()
+                    .iter()
+                    .find(|label| label.name() == name)
+                    .map(|label| label.value().to_string())
+            })
+            .collect();
+        key.and_then(|key| entry.series.get(&key).cloned())
+            .unwrap_or_default()
+    }
+}
+
+/// Encode metric families in the OpenMetrics 1.0 text format, attaching
+/// exemplars to histogram buckets
+pub fn encode(families: &[MetricFamily], exemplars: &Exemplars) -> String {
+    let mut out = String::new();
+    for family in families {
+        let name = family.name();
+        let kind = family.get_field_type();
+        // Counter families are named without their `_total` sample suffix
+        let family_name = match kind {
+            MetricType::COUNTER => name.strip_suffix("_total").unwrap_or(name),
+            _ => name,
+        };
+        let type_name = match kind {
+            MetricType::COUNTER => "counter",
+            MetricType::GAUGE => "gauge",
+            MetricType::HISTOGRAM => "histogram",
+            MetricType::SUMMARY => "summary",
+            MetricType::UNTYPED => "unknown",
+        };
+        let _ = writeln!(out, "# TYPE {} {}", family_name, type_name);
+        if !family.help().is_empty() {
+            let _ = writeln!(out, "# HELP {} {}", family_name, escape(family.help()));
+        }
+
+        for metric in family.get_metric() {
+            let labels = metric.get_label();
+            match kind {
+                MetricType::COUNTER => sample(
+                    &mut out,
+                    &format!("{}_total", family_name),
+                    labels,
+                    None,
+                    metric.get_counter().value(),
+                ),
+                MetricType::GAUGE => {
+                    sample(&mut out, name, labels, None, metric.get_gauge().value())
+                }
+                MetricType::UNTYPED => sample(&mut out, name, labels, None, metric.untyped.value()),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    let series = exemplars.series(name, metric);
+                    let bucket_name = format!("{}_bucket", name);
+                    let buckets = histogram
+                        .get_bucket()
+                        .iter()
+                        .map(|b| (format_value(b.upper_bound()), b.cumulative_count()))
+                        .chain([("+Inf".to_string(), histogram.get_sample_count())]);
+                    for (i, (le, count)) in buckets.enumerate() {
+                        write_sample(
+                            &mut out,
+                            &bucket_name,
+                            labels,
+                            Some(("le", &le)),
+                            count as f64,
+                        );
+                        if let Some(Some(exemplar)) = series.get(i) {
+                            let _ = write!(
+                                out,
+                                " # {{trace_id=\"{}\"}} {} {}",
+                                escape(&exemplar.trace_id),
+                                format_value(exemplar.value),
+                                exemplar.timestamp
+                            );
+                        }
+                        out.push('\n');
+                    }
+                    sample(
+                        &mut out,
+                        &format!("{}_count", name),
+                        labels,
+                        None,
+                        histogram.get_sample_count() as f64,
+                    );
+                    sample(
+                        &mut out,
+                        &format!("{}_sum", name),
+                        labels,
+                        None,
+                        histogram.get_sample_sum(),
+                    );
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        sample(
+                            &mut out,
+                            name,
+                            labels,
+                            Some(("quantile", &format_value(quantile.quantile()))),
+                            quantile.value(),
+                        );
+                    }
+                    sample(
+                        &mut out,
+                        &format!("{}_count", name),
+                        labels,
+                        None,
+                        summary.sample_count() as f64,
+                    );
+                    sample(
+                        &mut out,
+                        &format!("{}_sum", name),
+                        labels,
+                        None,
+                        summary.sample_sum(),
+                    );
+                }
+            }
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+fn sample(
+    out: &mut String,
+    name: &str,
+    labels: &[LabelPair],
+    extra: Option<(&str, &str)>,
+    value: f64,
+) {
+    write_sample(out, name, labels, extra, value);
+    out.push('\n');
+}
+
+/// Write a sample line without its line break, leaving room for an exemplar
+fn write_sample(
+    out: &mut String,
+    name: &str,
+    labels: &[LabelPair],
+    extra: Option<(&str, &str)>,
+    value: f64,
+) {
+    out.push_str(name);
+    let pairs: Vec<_> = labels
+        .iter()
+        .map(|label| (label.name(), label.value()))
+        .chain(extra)
+        .collect();
+    if !pairs.is_empty() {
+        out.push('{');
+        for (i, (name, value)) in pairs.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{}=\"{}\"", name, escape(value));
+        }
+        out.push('}');
+    }
+    let _ = write!(out, " {}", format_value(value));
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
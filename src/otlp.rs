@@ -0,0 +1,244 @@
+use axum::http::uri::InvalidUri;
+use opentelemetry_proto::tonic::collector::metrics::v1::{
+    metrics_service_client::MetricsServiceClient, ExportMetricsServiceRequest,
+};
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, InstrumentationScope, KeyValue};
+use opentelemetry_proto::tonic::metrics::v1::{
+    metric, number_data_point, summary_data_point, AggregationTemporality, Gauge, Histogram,
+    HistogramDataPoint, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics, Sum, Summary,
+    SummaryDataPoint,
+};
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tonic::transport::Channel;
+
+use crate::models::AppInfo;
+use crate::sinks::{MetricsSink, SinkFuture};
+
+/// Pushes the Prometheus registry to an OpenTelemetry collector over OTLP/gRPC
+#[derive(Debug, Clone)]
+pub struct OtlpMetricsExporter {
+    name: String,
+    client: MetricsServiceClient<Channel>,
+    resource: Resource,
+    /// Start of the cumulative series, in nanoseconds since the Unix epoch
+    start_time: u64,
+}
+
+impl OtlpMetricsExporter {
+    /// Create an exporter for `endpoint`, connecting on the first export
+    pub fn new(
+        endpoint: &str,
+        app_info: &AppInfo,
+        start_time: SystemTime,
+    ) -> Result<Self, InvalidUri> {
+        let channel = Channel::from_shared(endpoint.to_string())?
+            .connect_timeout(Duration::from_secs(5))
+            .timeout(Duration::from_secs(10))
+            .connect_lazy();
+        Ok(Self {
+            name: format!("otlp {}", endpoint),
+            client: MetricsServiceClient::new(channel),
+            resource: Resource {
+                attributes: vec![
+                    attribute("service.name", &app_info.name),
+                    attribute("service.version", &app_info.version),
+                    attribute("deployment.environment", &app_info.environment),
+                ],
+                dropped_attributes_count: 0,
+            },
+            start_time: unix_nanos(start_time),
+        })
+    }
+
+    /// Export the current value of every metric
+    pub async fn export(&self, families: &[MetricFamily]) -> Result<(), tonic::Status> {
+        let request = to_request(
+            families,
+            &self.resource,
+            self.start_time,
+            unix_nanos(SystemTime::now()),
+        );
+        self.client.clone().export(request).await?;
+        Ok(())
+    }
+}
+
+impl MetricsSink for OtlpMetricsExporter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn push<'a>(&'a self, families: &'a [MetricFamily]) -> SinkFuture<'a> {
+        Box::pin(async move {
+            self.export(families)
+                .await
+                .map_err(|status| status.message().to_string())
+        })
+    }
+}
+
+/// Convert gathered metric families into an OTLP export request
+///
+/// Counters become monotonic cumulative sums named without their `_total`
+/// suffix, histograms keep their explicit bucket bounds.
+pub fn to_request(
+    families: &[MetricFamily],
+    resource: &Resource,
+    start_time: u64,
+    time: u64,
+) -> ExportMetricsServiceRequest {
+    let metrics = families
+        .iter()
+        .map(|family| {
+            let name = family.name();
+            let points = family.get_metric();
+            let number = |value: f64, labels: &[LabelPair]| NumberDataPoint {
+                attributes: attributes(labels),
+                start_time_unix_nano: start_time,
+                time_unix_nano: time,
+                value: Some(number_data_point::Value::AsDouble(value)),
+                ..Default::default()
+            };
+
+            let (name, data) = match family.get_field_type() {
+                MetricType::COUNTER => (
+                    name.strip_suffix("_total").unwrap_or(name),
+                    metric::Data::Sum(Sum {
+                        data_points: points
+                            .iter()
+                            .map(|m| number(m.get_counter().value(), m.get_label()))
+                            .collect(),
+                        aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                        is_monotonic: true,
+                    }),
+                ),
+                MetricType::GAUGE | MetricType::UNTYPED => (
+                    name,
+                    metric::Data::Gauge(Gauge {
+                        data_points: points
+                            .iter()
+                            .map(|m| {
+                                let value = match family.get_field_type() {
+                                    MetricType::GAUGE => m.get_gauge().value(),
+                                    _ => m.untyped.value(),
+                                };
+                                number(value, m.get_label())
+                            })
+                            .collect(),
+                    }),
+                ),
+                MetricType::HISTOGRAM => (
+                    name,
+                    metric::Data::Histogram(Histogram {
+                        data_points: points
+                            .iter()
+                            .map(|m| {
+                                let histogram = m.get_histogram();
+                                let buckets = histogram.get_bucket();
+                                // OTLP counts each bucket on its own, Prometheus cumulatively
+                                let mut previous = 0;
+                                let mut bucket_counts: Vec<u64> = buckets
+                                    .iter()
+                                    .map(|bucket| {
+                                        let count = bucket.cumulative_count() - previous;
+                                        previous = bucket.cumulative_count();
+                                        count
+                                    })
+                                    .collect();
+                                bucket_counts.push(histogram.get_sample_count() - previous);
+                                HistogramDataPoint {
+                                    attributes: attributes(m.get_label()),
+                                    start_time_unix_nano: start_time,
+                                    time_unix_nano: time,
+                                    count: histogram.get_sample_count(),
+                                    sum: Some(histogram.get_sample_sum()),
+                                    bucket_counts,
+                                    explicit_bounds: buckets
+                                        .iter()
+                                        .map(|bucket| bucket.upper_bound())
+                                        .collect(),
+                                    ..Default::default()
+                                }
+                            })
+                            .collect(),
+                        aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                    }),
+                ),
+                MetricType::SUMMARY => (
+                    name,
+                    metric::Data::Summary(Summary {
+                        data_points: points
+                            .iter()
+                            .map(|m| {
+                                let summary = m.get_summary();
+                                SummaryDataPoint {
+                                    attributes: attributes(m.get_label()),
+                                    start_time_unix_nano: start_time,
+                                    time_unix_nano: time,
+                                    count: summary.sample_count(),
+                                    sum: summary.sample_sum(),
+                                    quantile_values: summary
+                                        .get_quantile()
+                                        .iter()
+                                        .map(|q| summary_data_point::ValueAtQuantile {
+                                            quantile: q.quantile(),
+                                            value: q.value(),
+                                        })
+                                        .collect(),
+                                    ..Default::default()
+                                }
+                            })
+                            .collect(),
+                    }),
+                ),
+            };
+
+            Metric {
+                name: name.to_string(),
+                description: family.help().to_string(),
+                data: Some(data),
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: Some(resource.clone()),
+            scope_metrics: vec![ScopeMetrics {
+                scope: Some(InstrumentationScope {
+                    name: "learn-rust".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    ..Default::default()
+                }),
+                metrics,
+                schema_url: String::new(),
+            }],
+            schema_url: String::new(),
+        }],
+    }
+}
+
+fn attributes(labels: &[LabelPair]) -> Vec<KeyValue> {
+    labels
+        .iter()
+        .map(|label| attribute(label.name(), label.value()))
+        .collect()
+}
+
+fn attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.to_string())),
+        }),
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use utoipa::ToSchema;
+
+/// Service account mount present in every Kubernetes pod by default
+const SERVICE_ACCOUNT_DIR: &str = "var/run/secrets/kubernetes.io/serviceaccount";
+
+/// cgroup path fragments left by container runtimes
+const CONTAINER_CGROUP_HINTS: &[&str] = &["kubepods", "docker", "containerd", "crio", "libpod"];
+
+/// Where the service is running
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Host,
+    Container,
+    Kubernetes,
+}
+
+/// Platform the service detected at startup
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RuntimeInfo {
+    pub platform: Platform,
+    /// Container ID taken from the cgroup path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kubernetes: Option<KubernetesInfo>,
+}
+
+/// Pod identity from the downward API and the service account mount
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct KubernetesInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod_ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_account: Option<String>,
+}
+
+impl RuntimeInfo {
+    /// Inspect the process environment and filesystem
+    pub fn detect() -> Self {
+        Self::detect_with(&std::env::vars().collect(), Path::new("/"))
+    }
+
+    /// Detect using the given environment and filesystem root
+    ///
+    /// Kubernetes is recognised by the service account mount or the
+    /// `KUBERNETES_SERVICE_HOST`, `POD_NAME` and `POD_NAMESPACE` variables.
+    /// Other containers are recognised by `/.dockerenv`, `/run/.containerenv`,
+    /// the `container` variable or container runtime names in the cgroup path.
+    pub fn detect_with(env: &HashMap<String, String>, root: &Path) -> Self {
+        let var = |name: &str| env.get(name).filter(|v| !v.is_empty()).cloned();
+        let service_account = root.join(SERVICE_ACCOUNT_DIR);
+        let cgroup = std::fs::read_to_string(root.join("proc/self/cgroup")).unwrap_or_default();
+        let container_id = container_id(&cgroup);
+
+        let in_kubernetes = service_account.is_dir()
+            || ["KUBERNETES_SERVICE_HOST", "POD_NAME", "POD_NAMESPACE"]
+                .iter()
+                .any(|name| var(name).is_some());
+        let in_container = in_kubernetes
+            || root.join(".dockerenv").exists()
+            || root.join("run/.containerenv").exists()
+            || var("container").is_some()
+            || container_id.is_some()
+            || CONTAINER_CGROUP_HINTS
+                .iter()
+                .any(|hint| cgroup.contains(hint));
+
+        let kubernetes = in_kubernetes.then(|| KubernetesInfo {
+            pod_name: var("POD_NAME").or_else(|| var("HOSTNAME")),
+            namespace: var("POD_NAMESPACE").or_else(|| {
+                std::fs::read_to_string(service_account.join("namespace"))
+                    .ok()
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+            }),
+            node_name: var("NODE_NAME"),
+            pod_ip: var("POD_IP"),
+            service_account: var("POD_SERVICE_ACCOUNT"),
+        });
+
+        let platform = if in_kubernetes {
+            Platform::Kubernetes
+        } else if in_container {
+            Platform::Container
+        } else {
+            Platform::Host
+        };
+
+        Self {
+            platform,
+            container_id,
+            kubernetes,
+        }
+    }
+}
+
+/// First 64 character hex segment of the cgroup paths, as runtimes name them
+fn container_id(cgroup: &str) -> Option<String> {
+    cgroup
+        .lines()
+        .filter_map(|line| line.splitn(3, ':').nth(2))
+        .flat_map(|path| path.split(['/', '-', '.', ':']))
+        .find(|segment| segment.len() == 64 && segment.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_string)
+}
@@ -0,0 +1,217 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, error, info, warn};
+
+use crate::config::{redact_value, Config, ConfigError, ConfigLoader};
+use crate::models::AppState;
+
+/// Keys (or key prefixes ending in `.`) that are only read at startup
+const RESTART_ONLY: &[&str] = &[
+    "server.",
+    "runtime.",
+    "app.",
+    "endpoints.",
+    "health.",
+    "metrics.",
+    "log.format",
+    "reload.",
+];
+
+type ReloadHook = Box<dyn Fn(&Config) + Send + Sync>;
+
+/// Reloads configuration on SIGHUP and config file changes
+///
+/// A reload re-runs the same [`ConfigLoader`] used at startup, validates the
+/// result and swaps it into [`AppState`]. Changes to keys that are only read
+/// at startup (server, runtime, app, endpoints, health checks, metrics, log
+/// format, reload) are logged and ignored until the next restart.
+pub struct ConfigReloader {
+    state: Arc<AppState>,
+    loader: ConfigLoader,
+    hooks: Vec<ReloadHook>,
+}
+
+impl ConfigReloader {
+    pub fn new(state: Arc<AppState>, loader: ConfigLoader) -> Self {
+        Self {
+            state,
+            loader,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Run a callback with the new configuration before it is swapped in
+    pub fn on_reload(mut self, hook: impl Fn(&Config) + Send + Sync + 'static) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Reload once, returning the new generation if anything changed
+    pub fn reload(&self) -> Result<Option<u64>, ConfigError> {
+        let (current, current_sources) = self.state.config_with_sources();
+        let (loaded, mut sources) = self.loader.load_with_sources()?;
+
+        let mut changes = Vec::new();
+        for (key, old, new) in current.diff(&loaded) {
+            if is_restart_only(&key) {
+                warn!(
+                    "[WARN] Config: {} changed to {} but requires a restart, keeping {}",
+                    key,
+                    redact_value(&key, &new),
+                    redact_value(&key, &old)
+                );
+                sources.set(key.clone(), current_sources.get(&key).clone());
+            } else {
+                changes.push((key, old, new));
+            }
+        }
+
+        if changes.is_empty() {
+            return Ok(None);
+        }
+
+        let next = keep_restart_only(&current, &loaded).map_err(|e| ConfigError::Invalid {
+            key: "reload".to_string(),
+            message: format!("cannot keep the restart-only settings: {}", e),
+        })?;
+        for (key, old, new) in &changes {
+            info!(
+                "[INFO] Config: {} changed from {} to {}",
+                key,
+                redact_value(key, old),
+                redact_value(key, new)
+            );
+        }
+        for hook in &self.hooks {
+            hook(&next);
+        }
+        Ok(Some(self.state.replace_config(next, sources)))
+    }
+
+    /// Watch for SIGHUP and config file changes in a background task
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move { self.run().await })
+    }
+
+    async fn run(self) {
+        let poll_interval = self.state.config().reload.poll_interval;
+        let mut ticker = tokio::time::interval(Duration::from_secs(poll_interval.max(1)));
+        let mut hangup = Hangup::new();
+        let mut fingerprint = self.fingerprint();
+
+        info!(
+            "[INFO] Config: watching for SIGHUP{}",
+            if poll_interval > 0 {
+                format!(" and file changes every {}s", poll_interval)
+            } else {
+                String::new()
+            }
+        );
+
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => {
+                    info!("[INFO] Config: SIGHUP received, reloading");
+                    self.apply();
+                    fingerprint = self.fingerprint();
+                }
+                _ = ticker.tick(), if poll_interval > 0 => {
+                    let current = self.fingerprint();
+                    if current != fingerprint {
+                        info!("[INFO] Config: file change detected, reloading");
+                        self.apply();
+                        fingerprint = self.fingerprint();
+                    }
+                }
+            }
+        }
+    }
+
+    fn apply(&self) {
+        match self.reload() {
+            Ok(Some(generation)) => {
+                info!("[INFO] Config: reloaded, now at generation {}", generation)
+            }
+            Ok(None) => debug!("Config: reload found no changes"),
+            Err(e) => error!(
+                "[ERROR] Config: reload rejected, keeping generation {}: {}",
+                self.state.config_generation(),
+                e
+            ),
+        }
+    }
+
+    /// Modification time and size of every file that feeds the configuration
+    fn fingerprint(&self) -> Vec<(PathBuf, Option<(SystemTime, u64)>)> {
+        let environment = self.state.config().app.environment.clone();
+        self.loader
+            .watched_files(&environment)
+            .into_iter()
+            .map(|path| {
+                let meta = std::fs::metadata(&path)
+                    .ok()
+                    .and_then(|m| Some((m.modified().ok()?, m.len())));
+                (path, meta)
+            })
+            .collect()
+    }
+}
+
+/// `loaded` with every restart-only section copied whole from `current`, so
+/// keys added or removed under them are reverted too
+fn keep_restart_only(current: &Config, loaded: &Config) -> Result<Config, serde_json::Error> {
+    let current = serde_json::to_value(current)?;
+    let mut next = serde_json::to_value(loaded)?;
+    for prefix in RESTART_ONLY {
+        let pointer = format!("/{}", prefix.trim_end_matches('.').replace('.', "/"));
+        if let (Some(kept), Some(slot)) = (current.pointer(&pointer), next.pointer_mut(&pointer)) {
+            *slot = kept.clone();
+        }
+    }
+    serde_json::from_value(next)
+}
+
+fn is_restart_only(key: &str) -> bool {
+    RESTART_ONLY.iter().any(|prefix| {
+        if prefix.ends_with('.') {
+            key.starts_with(prefix)
+        } else {
+            key == *prefix
+        }
+    })
+}
+
+/// SIGHUP listener, never firing on platforms without it
+struct Hangup {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+    fn new() -> Self {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let signal = signal(SignalKind::hangup())
+                .map_err(|e| warn!("[WARN] Config: cannot listen for SIGHUP: {}", e))
+                .ok();
+            Self { signal }
+        }
+        #[cfg(not(unix))]
+        {
+            Self {}
+        }
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = self.signal.as_mut() {
+            if signal.recv().await.is_some() {
+                return;
+            }
+            self.signal = None;
+        }
+        std::future::pending::<()>().await
+    }
+}
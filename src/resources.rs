@@ -0,0 +1,179 @@
+use std::sync::{Arc, Mutex};
+
+use crate::config::{Threshold, ThresholdsConfig};
+use crate::health::{CheckFuture, CheckOptions, CheckResult, HealthCheck, HealthRegistry};
+use crate::system::SystemSnapshot;
+
+/// Resource signal compared against a threshold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Memory,
+    CpuLoad,
+    OpenFds,
+    EventLoopLag,
+}
+
+impl Resource {
+    pub const ALL: [Resource; 4] = [
+        Resource::Memory,
+        Resource::CpuLoad,
+        Resource::OpenFds,
+        Resource::EventLoopLag,
+    ];
+
+    /// Check name and metric label
+    pub fn name(self) -> &'static str {
+        match self {
+            Resource::Memory => "memory",
+            Resource::CpuLoad => "cpu_load",
+            Resource::OpenFds => "open_fds",
+            Resource::EventLoopLag => "event_loop_lag",
+        }
+    }
+
+    fn threshold(self, thresholds: &ThresholdsConfig) -> Threshold {
+        match self {
+            Resource::Memory => thresholds.memory_percent,
+            Resource::CpuLoad => thresholds.cpu_load,
+            Resource::OpenFds => thresholds.open_fds,
+            Resource::EventLoopLag => thresholds.event_loop_lag_ms,
+        }
+    }
+
+    /// Current value in the unit its threshold is configured in
+    fn measure(self, snapshot: &SystemSnapshot) -> Option<f64> {
+        match self {
+            Resource::Memory => Some(snapshot.memory().percent),
+            Resource::CpuLoad => Some(snapshot.cpu_load()),
+            Resource::OpenFds => snapshot.open_fds.map(|fds| fds as f64),
+            Resource::EventLoopLag => Some(snapshot.event_loop_lag.as_secs_f64() * 1000.0),
+        }
+    }
+
+    fn describe(self, value: f64) -> String {
+        match self {
+            Resource::Memory => format!("memory at {:.1}%", value),
+            Resource::CpuLoad => format!("cpu load at {:.2} per cpu", value),
+            Resource::OpenFds => format!("{} open file descriptors", value),
+            Resource::EventLoopLag => format!("event loop lag at {:.1}ms", value),
+        }
+    }
+}
+
+/// Severity of a resource signal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    #[default]
+    Ok,
+    Degraded,
+    Unhealthy,
+}
+
+/// Last evaluated value and level of every resource
+#[derive(Debug, Default)]
+pub struct ResourceMonitor {
+    state: Mutex<[(Level, Option<f64>); 4]>,
+}
+
+impl ResourceMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compare a snapshot against the thresholds and update the gauges
+    ///
+    /// A level is entered as soon as its threshold is reached, but only left
+    /// once the value drops `hysteresis` below it, so values hovering around
+    /// a threshold do not flap.
+    pub fn evaluate(&self, snapshot: &SystemSnapshot, thresholds: &ThresholdsConfig) {
+        let mut state = self.state.lock().unwrap();
+        for (i, resource) in Resource::ALL.into_iter().enumerate() {
+            let value = resource.measure(snapshot);
+            let level = match value {
+                Some(value) => next_level(
+                    state[i].0,
+                    value,
+                    resource.threshold(thresholds),
+                    thresholds.hysteresis,
+                ),
+                None => Level::Ok,
+            };
+            state[i] = (level, value);
+            crate::metrics::record_resource(resource.name(), value, level as i64);
+        }
+    }
+
+    /// Current level and last measured value of a resource
+    pub fn level(&self, resource: Resource) -> (Level, Option<f64>) {
+        let index = Resource::ALL.iter().position(|r| *r == resource).unwrap();
+        self.state.lock().unwrap()[index]
+    }
+}
+
+fn next_level(current: Level, value: f64, threshold: Threshold, hysteresis: f64) -> Level {
+    let holds = |level: Level, limit: f64| {
+        let limit = if current >= level {
+            limit * (1.0 - hysteresis)
+        } else {
+            limit
+        };
+        limit > 0.0 && value >= limit
+    };
+    if holds(Level::Unhealthy, threshold.unhealthy) {
+        Level::Unhealthy
+    } else if holds(Level::Degraded, threshold.degraded) {
+        Level::Degraded
+    } else {
+        Level::Ok
+    }
+}
+
+/// Health check reporting the level a [`ResourceMonitor`] last evaluated
+pub struct ResourceCheck {
+    resource: Resource,
+    monitor: Arc<ResourceMonitor>,
+}
+
+impl HealthCheck for ResourceCheck {
+    fn name(&self) -> &str {
+        self.resource.name()
+    }
+
+    fn check(&self) -> CheckFuture<'_> {
+        Box::pin(async move {
+            let (level, value) = self.monitor.level(self.resource);
+            let Some(value) = value else {
+                return CheckResult::pass();
+            };
+            let message = self.resource.describe(value);
+            match level {
+                Level::Ok => CheckResult::pass().with_message(message),
+                Level::Degraded => {
+                    CheckResult::warn(format!("{}, above the degraded threshold", message))
+                }
+                Level::Unhealthy => {
+                    CheckResult::fail(format!("{}, above the unhealthy threshold", message))
+                }
+            }
+        })
+    }
+}
+
+/// Register a check for every resource with a threshold configured
+pub fn register_resource_checks(
+    registry: &HealthRegistry,
+    monitor: &Arc<ResourceMonitor>,
+    thresholds: &ThresholdsConfig,
+) {
+    for resource in Resource::ALL {
+        if resource.threshold(thresholds).is_enabled() {
+            registry.register(
+                ResourceCheck {
+                    resource,
+                    monitor: monitor.clone(),
+                },
+                CheckOptions::critical(),
+            );
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::{CounterVec, IntCounterVec, IntGauge, Opts};
+use std::sync::Mutex;
+use tokio::runtime::{Builder, Handle, Runtime};
+
+use crate::config::RuntimeConfig;
+
+/// Build the multi-threaded runtime the server runs on
+///
+/// With `runtime.worker_threads = 0` tokio starts one worker per CPU, or
+/// `TOKIO_WORKER_THREADS` when it is set.
+pub fn build(config: &RuntimeConfig) -> std::io::Result<Runtime> {
+    let mut builder = Builder::new_multi_thread();
+    builder
+        .enable_all()
+        .thread_name("learn-rust-worker")
+        .max_blocking_threads(config.max_blocking_threads)
+        .thread_stack_size(config.thread_stack_size);
+    if config.worker_threads > 0 {
+        builder.worker_threads(config.worker_threads);
+    }
+    builder.build()
+}
+
+/// Prometheus collector of the scheduler metrics of a tokio runtime
+///
+/// Values are read from the runtime on every scrape. Poll counts and the
+/// blocking pool are only available in builds with `--cfg tokio_unstable`.
+pub struct RuntimeCollector {
+    handle: Handle,
+    descs: Vec<Desc>,
+    /// Held while catching up, so concurrent scrapes don't count twice
+    update: Mutex<()>,
+    workers: IntGauge,
+    alive_tasks: IntGauge,
+    global_queue_depth: IntGauge,
+    busy_seconds: CounterVec,
+    parks: IntCounterVec,
+    #[cfg(tokio_unstable)]
+    unstable: UnstableMetrics,
+}
+
+/// Metrics behind tokio's unstable metrics API
+#[cfg(tokio_unstable)]
+struct UnstableMetrics {
+    blocking_threads: IntGauge,
+    idle_blocking_threads: IntGauge,
+    blocking_queue_depth: IntGauge,
+    spawned_tasks: prometheus::IntCounter,
+    polls: IntCounterVec,
+    mean_poll_seconds: prometheus::GaugeVec,
+}
+
+impl RuntimeCollector {
+    pub fn new(handle: Handle) -> Self {
+        let workers = IntGauge::new("tokio_workers", "Worker threads of the runtime")
+            .expect("metric can be created");
+        let alive_tasks = IntGauge::new(
+            "tokio_alive_tasks",
+            "Tasks spawned on the runtime that have not completed yet",
+        )
+        .expect("metric can be created");
+        let global_queue_depth = IntGauge::new(
+            "tokio_global_queue_depth",
+            "Tasks waiting in the runtime's global queue",
+        )
+        .expect("metric can be created");
+        let busy_seconds = CounterVec::new(
+            Opts::new(
+                "tokio_worker_busy_seconds_total",
+                "Time each worker spent running tasks",
+            ),
+            &["worker"],
+        )
+        .expect("metric can be created");
+        let parks = IntCounterVec::new(
+            Opts::new(
+                "tokio_worker_parks_total",
+                "Times each worker ran out of work and parked",
+            ),
+            &["worker"],
+        )
+        .expect("metric can be created");
+        #[cfg(tokio_unstable)]
+        let unstable = UnstableMetrics::new();
+
+        let mut descs = Vec::new();
+        let collectors: [&dyn Collector; 5] = [
+            &workers,
+            &alive_tasks,
+            &global_queue_depth,
+            &busy_seconds,
+            &parks,
+        ];
+        for collector in collectors {
+            descs.extend(collector.desc().into_iter().cloned());
+        }
+        #[cfg(tokio_unstable)]
+        for collector in unstable.collectors() {
+            descs.extend(collector.desc().into_iter().cloned());
+        }
+
+        Self {
+            handle,
+            descs,
+            update: Mutex::new(()),
+            workers,
+            alive_tasks,
+            global_queue_depth,
+            busy_seconds,
+            parks,
+            #[cfg(tokio_unstable)]
+            unstable,
+        }
+    }
+}
+
+impl Collector for RuntimeCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let _update = self.update.lock().unwrap();
+        let metrics = self.handle.metrics();
+        self.workers.set(metrics.num_workers() as i64);
+        self.alive_tasks.set(metrics.num_alive_tasks() as i64);
+        self.global_queue_depth
+            .set(metrics.global_queue_depth() as i64);
+        for worker in 0..metrics.num_workers() {
+            let label = worker.to_string();
+            // The runtime keeps running totals, counters catch up to them
+            let busy = self.busy_seconds.with_label_values(&[&label]);
+            let total = metrics.worker_total_busy_duration(worker).as_secs_f64();
+            busy.inc_by((total - busy.get()).max(0.0));
+            let parks = self.parks.with_label_values(&[&label]);
+            parks.inc_by(
+                metrics
+                    .worker_park_count(worker)
+                    .saturating_sub(parks.get()),
+            );
+        }
+        #[cfg(tokio_unstable)]
+        self.unstable.update(&metrics);
+
+        let mut families = Vec::new();
+        let collectors: [&dyn Collector; 5] = [
+            &self.workers,
+            &self.alive_tasks,
+            &self.global_queue_depth,
+            &self.busy_seconds,
+            &self.parks,
+        ];
+        for collector in collectors {
+            families.extend(collector.collect());
+        }
+        #[cfg(tokio_unstable)]
+        for collector in self.unstable.collectors() {
+            families.extend(collector.collect());
+        }
+        families
+    }
+}
+
+#[cfg(tokio_unstable)]
+impl UnstableMetrics {
+    fn new() -> Self {
+        Self {
+            blocking_threads: IntGauge::new(
+                "tokio_blocking_threads",
+                "Threads of the blocking pool",
+            )
+            .expect("metric can be created"),
+            idle_blocking_threads: IntGauge::new(
+                "tokio_blocking_idle_threads",
+                "Threads of the blocking pool waiting for work",
+            )
+            .expect("metric can be created"),
+            blocking_queue_depth: IntGauge::new(
+                "tokio_blocking_queue_depth",
+                "Tasks waiting for a blocking pool thread",
+            )
+            .expect("metric can be created"),
+            spawned_tasks: prometheus::IntCounter::new(
+                "tokio_spawned_tasks_total",
+                "Tasks spawned on the runtime",
+            )
+            .expect("metric can be created"),
+            polls: IntCounterVec::new(
+                Opts::new("tokio_worker_polls_total", "Tasks polled by each worker"),
+                &["worker"],
+            )
+            .expect("metric can be created"),
+            mean_poll_seconds: prometheus::GaugeVec::new(
+                Opts::new(
+                    "tokio_worker_mean_poll_seconds",
+                    "Moving average of the time each worker spends polling a task",
+                ),
+                &["worker"],
+            )
+            .expect("metric can be created"),
+        }
+    }
+
+    fn collectors(&self) -> [&dyn Collector; 6] {
+        [
+            &self.blocking_threads,
+            &self.idle_blocking_threads,
+            &self.blocking_queue_depth,
+            &self.spawned_tasks,
+            &self.polls,
+            &self.mean_poll_seconds,
+        ]
+    }
+
+    fn update(&self, metrics: &tokio::runtime::RuntimeMetrics) {
+        self.blocking_threads
+            .set(metrics.num_blocking_threads() as i64);
+        self.idle_blocking_threads
+            .set(metrics.num_idle_blocking_threads() as i64);
+        self.blocking_queue_depth
+            .set(metrics.blocking_queue_depth() as i64);
+        self.spawned_tasks.inc_by(
+            metrics
+                .spawned_tasks_count()
+                .saturating_sub(self.spawned_tasks.get()),
+        );
+        for worker in 0..metrics.num_workers() {
+            let label = worker.to_string();
+            let polls = self.polls.with_label_values(&[&label]);
+            polls.inc_by(
+                metrics
+                    .worker_poll_count(worker)
+                    .saturating_sub(polls.get()),
+            );
+            self.mean_poll_seconds
+                .with_label_values(&[&label])
+                .set(metrics.worker_mean_poll_time(worker).as_secs_f64());
+        }
+    }
+}
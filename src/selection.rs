@@ -0,0 +1,164 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use utoipa::IntoParams;
+
+/// Section and field selection accepted by `/info` and `/healthz`
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SectionQuery {
+    /// Comma-separated top-level sections to return, e.g. `system,process`
+    pub include: Option<String>,
+    /// Comma-separated dotted fields to return, e.g. `system.memory.percent`
+    pub fields: Option<String>,
+}
+
+/// Parts of a response a client asked for
+///
+/// Sections that are not selected are never collected. Fields narrow the
+/// output further to individual values inside their section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// Sections to collect, none when everything is
+    sections: Option<BTreeSet<String>>,
+    /// Sections returned in full
+    include: BTreeSet<String>,
+    fields: Vec<String>,
+}
+
+impl Selection {
+    /// Everything, as returned when no selection is given
+    pub fn all() -> Self {
+        Self {
+            sections: None,
+            include: BTreeSet::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Parse a query against the sections the endpoint offers
+    pub fn parse(query: &SectionQuery, known: &[&str]) -> Result<Self, String> {
+        let list = |raw: &Option<String>| -> Vec<String> {
+            raw.as_deref()
+                .unwrap_or("")
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let include = list(&query.include);
+        let fields = list(&query.fields);
+        if include.is_empty() && fields.is_empty() {
+            return Ok(Self::all());
+        }
+
+        if let Some(nested) = include.iter().find(|name| name.contains('.')) {
+            return Err(format!(
+                "include takes section names, use fields for '{}'",
+                nested
+            ));
+        }
+
+        let mut sections = BTreeSet::new();
+        for name in include.iter().chain(&fields) {
+            let section = name.split('.').next().unwrap_or_default();
+            if known.contains(&section) {
+                sections.insert(section.to_string());
+            } else if include.contains(name) || name.contains('.') {
+                return Err(format!(
+                    "unknown section '{}', expected one of: {}",
+                    section,
+                    known.join(", ")
+                ));
+            }
+            // Other top-level fields, such as `status`, are looked up in the
+            // response
+        }
+
+        Ok(Self {
+            sections: Some(sections),
+            include: include.into_iter().collect(),
+            fields,
+        })
+    }
+
+    /// Whether a section has to be collected
+    pub fn wants(&self, section: &str) -> bool {
+        self.sections
+            .as_ref()
+            .is_none_or(|sections| sections.contains(section))
+    }
+
+    /// Narrow a serialized response down to the selected fields
+    ///
+    /// `always` lists keys kept regardless of the selection, such as `status`.
+    pub fn apply(&self, value: Value, always: &[&str]) -> Result<Value, String> {
+        if self.fields.is_empty() {
+            return Ok(value);
+        }
+        let Value::Object(source) = value else {
+            return Ok(value);
+        };
+
+        let mut out = Map::new();
+        for key in always {
+            if let Some(v) = source.get(*key) {
+                out.insert(key.to_string(), v.clone());
+            }
+        }
+        for field in &self.fields {
+            let path: Vec<&str> = field.split('.').collect();
+            let found = lookup(&source, field, &path)?;
+            insert_path(&mut out, &path, found.clone());
+        }
+        for section in &self.include {
+            if let Some(v) = source.get(section) {
+                out.insert(section.clone(), v.clone());
+            }
+        }
+        Ok(Value::Object(out))
+    }
+}
+
+/// Value at `path`, which can't go through lists since the selected
+/// fields are rebuilt as nested objects
+fn lookup<'a>(
+    source: &'a Map<String, Value>,
+    field: &str,
+    path: &[&str],
+) -> Result<&'a Value, String> {
+    let unknown = || format!("unknown field '{}'", field);
+    let (first, rest) = path.split_first().ok_or_else(unknown)?;
+    let mut value = source.get(*first).ok_or_else(unknown)?;
+    for (depth, key) in rest.iter().enumerate() {
+        value = match value {
+            Value::Object(map) => map.get(*key).ok_or_else(unknown)?,
+            Value::Array(_) => {
+                return Err(format!(
+                    "cannot select inside lists, select '{}' instead",
+                    path[..=depth].join(".")
+                ))
+            }
+            _ => return Err(unknown()),
+        };
+    }
+    Ok(value)
+}
+
+fn insert_path(map: &mut Map<String, Value>, path: &[&str], value: Value) {
+    match path {
+        [] => {}
+        [key] => {
+            map.insert(key.to_string(), value);
+        }
+        [key, rest @ ..] => {
+            let entry = map
+                .entry(key.to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(child) = entry {
+                insert_path(child, rest, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use axum::Router;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::models::AppState;
+
+/// How the server stopped after a shutdown signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownOutcome {
+    /// Every in-flight request completed
+    Drained,
+    /// The drain timeout elapsed with requests still running
+    TimedOut,
+}
+
+/// Wait for SIGINT (Ctrl+C) or SIGTERM
+pub async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C signal handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM signal handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("[INFO] Shutdown: SIGINT received"),
+        _ = terminate => info!("[INFO] Shutdown: SIGTERM received"),
+    }
+}
+
+/// Serve `app` until `signal` resolves, then drain in-flight requests
+///
+/// When the signal fires the state is marked as draining so readiness starts
+/// failing. The listener keeps accepting for `server.shutdown_delay` seconds
+/// while load balancers stop routing to the pod, then closes, and in-flight
+/// requests get up to `server.drain_timeout` seconds to finish.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    state: Arc<AppState>,
+    signal: impl Future<Output = ()> + Send + 'static,
+) -> std::io::Result<ShutdownOutcome> {
+    let config = state.config();
+    let delay = Duration::from_secs(config.server.shutdown_delay);
+    let drain_timeout = Duration::from_secs(config.server.drain_timeout);
+    let draining = Arc::new(Notify::new());
+
+    let shutdown = {
+        let draining = draining.clone();
+        async move {
+            signal.await;
+            state.start_draining();
+            info!(
+                "[INFO] Shutdown: readiness failing, closing listener in {}s",
+                delay.as_secs()
+            );
+            tokio::time::sleep(delay).await;
+            info!(
+                "[INFO] Shutdown: draining in-flight requests, timeout {}s",
+                drain_timeout.as_secs()
+            );
+            draining.notify_one();
+        }
+    };
+
+    let server = axum::serve(listener, app).with_graceful_shutdown(shutdown);
+
+    tokio::select! {
+        result = server => {
+            result?;
+            info!("[INFO] Shutdown: all requests drained");
+            Ok(ShutdownOutcome::Drained)
+        }
+        _ = async {
+            draining.notified().await;
+            tokio::time::sleep(drain_timeout).await;
+        } => {
+            warn!("[WARN] Shutdown: drain timeout elapsed, dropping remaining connections");
+            Ok(ShutdownOutcome::TimedOut)
+        }
+    }
+}
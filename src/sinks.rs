@@ -0,0 +1,450 @@
+use axum::body::Body;
+use axum::http::{header, Method};
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
+use prometheus::{Encoder, TextEncoder};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+
+use crate::config::{MetricsSinkConfig, MetricsSinkKind};
+use crate::http_client;
+use crate::models::AppState;
+
+/// Pushes taking longer than this fail
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest StatsD datagram, small enough to avoid IP fragmentation
+const MAX_DATAGRAM: usize = 1432;
+
+/// Boxed future returned by [`MetricsSink::push`]
+pub type SinkFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+/// A destination the metrics registry is pushed to
+///
+/// Implementations only need to deliver the gathered families; scheduling,
+/// timeouts and logging are handled by [`MetricsExport`].
+pub trait MetricsSink: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn push<'a>(&'a self, families: &'a [MetricFamily]) -> SinkFuture<'a>;
+}
+
+/// Build the sinks listed under `metrics.sinks`, plus OTLP when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is set
+pub fn configured_sinks(state: &AppState) -> Vec<Box<dyn MetricsSink>> {
+    let instance = state.system.snapshot().hostname.clone();
+    #[allow(unused_mut)]
+    let mut sinks: Vec<Box<dyn MetricsSink>> = state
+        .config()
+        .metrics
+        .sinks
+        .iter()
+        .map(|sink| from_config(sink, &instance))
+        .collect();
+
+    #[cfg(feature = "telemetry")]
+    if let Some(endpoint) = crate::telemetry::otlp_endpoint() {
+        match crate::otlp::OtlpMetricsExporter::new(&endpoint, &state.app_info, state.start_time) {
+            Ok(exporter) => sinks.push(Box::new(exporter)),
+            Err(e) => tracing::warn!(
+                "[WARN] OpenTelemetry: Invalid OTLP endpoint {}: {}",
+                endpoint,
+                e
+            ),
+        }
+    }
+    sinks
+}
+
+fn from_config(config: &MetricsSinkConfig, instance: &str) -> Box<dyn MetricsSink> {
+    match config.kind {
+        MetricsSinkKind::Pushgateway => {
+            Box::new(PushgatewaySink::new(&config.target, &config.job).instance(instance))
+        }
+        MetricsSinkKind::Statsd => Box::new(StatsdSink::new(&config.target, StatsdFormat::Plain)),
+        MetricsSinkKind::Dogstatsd => {
+            Box::new(StatsdSink::new(&config.target, StatsdFormat::Dogstatsd))
+        }
+    }
+}
+
+/// Periodic push of [`crate::metrics::REGISTRY`] to every sink
+///
+/// Sinks are pushed every `metrics.export_interval` seconds and once more on
+/// [`shutdown`](Self::shutdown), so short-lived processes still report their
+/// final values.
+pub struct MetricsExport {
+    sinks: Arc<Vec<Box<dyn MetricsSink>>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl MetricsExport {
+    /// Start pushing to the configured sinks
+    pub fn start(state: &AppState) -> Self {
+        let interval = Duration::from_secs(state.config().metrics.export_interval);
+        Self::with_sinks(configured_sinks(state), interval)
+    }
+
+    /// Start pushing to `sinks` every `interval`, doing nothing without sinks
+    pub fn with_sinks(sinks: Vec<Box<dyn MetricsSink>>, interval: Duration) -> Self {
+        for sink in &sinks {
+            tracing::info!(
+                "[INFO] Metrics: Pushing to {} every {}s",
+                sink.name(),
+                interval.as_secs()
+            );
+        }
+        let sinks = Arc::new(sinks);
+        let task = (!sinks.is_empty()).then(|| {
+            let sinks = sinks.clone();
+            tokio::spawn(async move {
+                let mut ticker =
+                    tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+                loop {
+                    ticker.tick().await;
+                    push_all(&sinks).await;
+                }
+            })
+        });
+        Self { sinks, task }
+    }
+
+    /// Push the current values to every sink
+    pub async fn flush(&self) {
+        push_all(&self.sinks).await;
+    }
+
+    /// Stop the periodic push and flush the final values
+    pub async fn shutdown(self) {
+        if let Some(task) = self.task {
+            task.abort();
+            push_all(&self.sinks).await;
+        }
+    }
+}
+
+async fn push_all(sinks: &[Box<dyn MetricsSink>]) {
+    if sinks.is_empty() {
+        return;
+    }
+    let families = crate::metrics::REGISTRY.gather();
+    for sink in sinks {
+        let result = match tokio::time::timeout(PUSH_TIMEOUT, sink.push(&families)).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("timed out after {}s", PUSH_TIMEOUT.as_secs())),
+        };
+        if let Err(e) = result {
+            tracing::warn!("[WARN] Metrics: Failed to push to {}: {}", sink.name(), e);
+        }
+    }
+}
+
+/// Replaces the metrics of a Prometheus Pushgateway group on every push
+pub struct PushgatewaySink {
+    name: String,
+    url: String,
+    job: String,
+    instance: Option<String>,
+}
+
+impl PushgatewaySink {
+    pub fn new(url: impl Into<String>, job: impl Into<String>) -> Self {
+        let url = url.into();
+        Self {
+            name: format!("pushgateway {}", url),
+            url,
+            job: job.into(),
+            instance: None,
+        }
+    }
+
+    /// Group the metrics by `instance` too, so replicas don't overwrite each other
+    pub fn instance(mut self, instance: impl Into<String>) -> Self {
+        self.instance = Some(instance.into()).filter(|instance| !instance.is_empty());
+        self
+    }
+
+    /// URL of the grouping key, e.g. `<url>/metrics/job/learn-rust/instance/pod-1`
+    fn group_url(&self) -> String {
+        let mut url = format!("{}/metrics", self.url.trim_end_matches('/'));
+        url.push_str(&group_segment("job", &self.job));
+        if let Some(instance) = &self.instance {
+            url.push_str(&group_segment("instance", instance));
+        }
+        url
+    }
+
+    async fn put(&self, body: Vec<u8>, content_type: &str) -> Result<(), String> {
+        let (status, _) = http_client::request(
+            Method::PUT,
+            &self.group_url(),
+            &[
+                (header::CONTENT_TYPE, content_type),
+                (header::USER_AGENT, "learn-rust-metrics"),
+            ],
+            Body::from(body),
+        )
+        .await?;
+        if !status.is_success() {
+            return Err(format!("PUT returned {}", status));
+        }
+        Ok(())
+    }
+}
+
+impl MetricsSink for PushgatewaySink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn push<'a>(&'a self, families: &'a [MetricFamily]) -> SinkFuture<'a> {
+        Box::pin(async move {
+            let encoder = TextEncoder::new();
+            let mut body = Vec::new();
+            encoder
+                .encode(families, &mut body)
+                .map_err(|e| e.to_string())?;
+            self.put(body, encoder.format_type()).await
+        })
+    }
+}
+
+/// `/<label>/<value>` segment of a grouping key
+///
+/// Values that aren't plain URL characters, such as ones containing `/`,
+/// are sent in the Pushgateway's `@base64` form.
+fn group_segment(label: &str, value: &str) -> String {
+    use base64::Engine as _;
+
+    let plain = !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~'));
+    if plain {
+        format!("/{}/{}", label, value)
+    } else {
+        let encoded = base64::engine::general_purpose::URL_SAFE.encode(value);
+        // An empty value still needs a segment
+        let encoded = if encoded.is_empty() {
+            "=".to_string()
+        } else {
+            encoded
+        };
+        format!("/{}@base64/{}", label, encoded)
+    }
+}
+
+/// How labels are sent to a StatsD agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsdFormat {
+    /// Label values are appended to the metric name, e.g. `http_requests_total.GET`
+    Plain,
+    /// Labels are sent as DogStatsD tags, e.g. `|#method:GET`
+    Dogstatsd,
+}
+
+/// Sends the metrics to a StatsD or DogStatsD agent over UDP
+///
+/// Gauges are sent as gauges. Counters, and the count and sum of
+/// histograms, are sent as counters incremented by the change since the
+/// previous push. Histogram buckets have no StatsD equivalent and are not
+/// sent.
+pub struct StatsdSink {
+    name: String,
+    target: String,
+    format: StatsdFormat,
+    /// Last value sent of every counter reported by the previous push, by
+    /// series and tags
+    sent: Mutex<HashMap<String, f64>>,
+}
+
+impl StatsdSink {
+    pub fn new(target: impl Into<String>, format: StatsdFormat) -> Self {
+        let target = target.into();
+        let kind = match format {
+            StatsdFormat::Plain => "statsd",
+            StatsdFormat::Dogstatsd => "dogstatsd",
+        };
+        Self {
+            name: format!("{} {}", kind, target),
+            target,
+            format,
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// StatsD lines for `families`, with the counter values they send
+    fn lines(&self, families: &[MetricFamily]) -> (Vec<String>, Vec<(String, f64)>) {
+        let sent = self.sent.lock().unwrap();
+        let mut lines = Vec::new();
+        let mut counters = Vec::new();
+        let mut counter = |name: &str, labels: &[LabelPair], value: f64| {
+            let series = self.series(name, labels);
+            let tags = self.tags(labels);
+            let key = format!("{}{}", series, tags);
+            // A value below the last one sent means the counter was reset
+            let delta = match sent.get(&key) {
+                Some(previous) if *previous <= value => value - previous,
+                _ => value,
+            };
+            if delta > 0.0 {
+                lines.push(format!("{}:{}|c{}", series, delta, tags));
+            }
+            counters.push((key, value));
+        };
+        let mut gauges = Vec::new();
+
+        for family in families {
+            let name = family.name();
+            for metric in family.get_metric() {
+                let labels = metric.get_label();
+                match family.get_field_type() {
+                    MetricType::COUNTER => counter(name, labels, metric.get_counter().value()),
+                    MetricType::GAUGE => gauges.push((name, labels, metric.get_gauge().value())),
+                    MetricType::UNTYPED => gauges.push((name, labels, metric.untyped.value())),
+                    MetricType::HISTOGRAM => {
+                        let histogram = metric.get_histogram();
+                        counter(
+                            &format!("{}_count", name),
+                            labels,
+                            histogram.get_sample_count() as f64,
+                        );
+                        counter(&format!("{}_sum", name), labels, histogram.get_sample_sum());
+                    }
+                    MetricType::SUMMARY => {
+                        let summary = metric.get_summary();
+                        counter(
+                            &format!("{}_count", name),
+                            labels,
+                            summary.sample_count() as f64,
+                        );
+                        counter(&format!("{}_sum", name), labels, summary.sample_sum());
+                    }
+                }
+            }
+        }
+
+        for (name, labels, value) in gauges {
+            let series = self.series(name, labels);
+            let tags = self.tags(labels);
+            // A signed gauge value is applied as a change, so reset to 0 first
+            if value < 0.0 {
+                lines.push(format!("{}:0|g{}", series, tags));
+            }
+            lines.push(format!("{}:{}|g{}", series, value, tags));
+        }
+        (lines, counters)
+    }
+
+    /// Metric name of a series, carrying the label values in the plain format
+    fn series(&self, name: &str, labels: &[LabelPair]) -> String {
+        let mut series = sanitize(name);
+        if self.format == StatsdFormat::Plain {
+            for label in labels.iter().filter(|label| !label.value().is_empty()) {
+                series.push('.');
+                series.push_str(&sanitize(label.value()));
+            }
+        }
+        series
+    }
+
+    /// DogStatsD tags suffix of a series, empty in the plain format
+    fn tags(&self, labels: &[LabelPair]) -> String {
+        if self.format == StatsdFormat::Plain || labels.is_empty() {
+            return String::new();
+        }
+        let tags: Vec<String> = labels
+            .iter()
+            .map(|label| format!("{}:{}", label.name(), sanitize_tag(label.value())))
+            .collect();
+        format!("|#{}", tags.join(","))
+    }
+
+    async fn send(&self, lines: &[String]) -> Result<(), String> {
+        let addr = tokio::net::lookup_host(&self.target)
+            .await
+            .map_err(|e| format!("resolve {}: {}", self.target, e))?
+            .next()
+            .ok_or_else(|| format!("{} has no addresses", self.target))?;
+        let local: SocketAddr = match addr {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+        let socket = UdpSocket::bind(local).await.map_err(|e| e.to_string())?;
+
+        for packet in packets(lines) {
+            socket
+                .send_to(packet.as_bytes(), addr)
+                .await
+                .map_err(|e| format!("send to {}: {}", addr, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl MetricsSink for StatsdSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn push<'a>(&'a self, families: &'a [MetricFamily]) -> SinkFuture<'a> {
+        Box::pin(async move {
+            let (lines, counters) = self.lines(families);
+            self.send(&lines).await?;
+            // Only remember counters once they were sent, so a failed push is
+            // caught up on the next one. Series that stopped reporting are
+            // dropped, so label churn doesn't grow the map.
+            *self.sent.lock().unwrap() = counters.into_iter().collect();
+            Ok(())
+        })
+    }
+}
+
+/// Join lines into newline separated datagrams of at most [`MAX_DATAGRAM`] bytes
+fn packets(lines: &[String]) -> Vec<String> {
+    let mut packets = Vec::new();
+    let mut packet = String::new();
+    for line in lines {
+        if !packet.is_empty() && packet.len() + 1 + line.len() > MAX_DATAGRAM {
+            packets.push(std::mem::take(&mut packet));
+        }
+        if !packet.is_empty() {
+            packet.push('\n');
+        }
+        packet.push_str(line);
+    }
+    if !packet.is_empty() {
+        packets.push(packet);
+    }
+    packets
+}
+
+/// Replace the characters StatsD reserves in metric names
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Replace the characters DogStatsD reserves in tag values
+fn sanitize_tag(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            ',' | '|' | '#' | '\n' => '_',
+            c => c,
+        })
+        .collect()
+}
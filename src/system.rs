@@ -0,0 +1,289 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use sysinfo::{CpuRefreshKind, Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use tracing::debug;
+
+use crate::cgroup::{Cgroup, CgroupStats};
+use crate::models::{AppState, MemoryInfo, MemorySource};
+
+/// Host metrics captured by the last [`SystemSampler`] refresh
+#[derive(Debug, Clone)]
+pub struct SystemSnapshot {
+    pub total_memory: u64,
+    pub available_memory: u64,
+    pub cpu_count: usize,
+    pub hostname: String,
+    /// Container limits and usage, none when not running in a cgroup
+    pub cgroup: Option<CgroupStats>,
+    /// One minute load average of the host
+    pub load_average: f64,
+    /// Open file descriptors of the process, where the platform reports them
+    pub open_fds: Option<u64>,
+    /// Largest event loop lag measured since the previous snapshot
+    pub event_loop_lag: Duration,
+    /// The service's own process
+    pub process: ProcessSnapshot,
+    pub sampled_at: Instant,
+}
+
+/// Resource usage of the current process
+#[derive(Debug, Clone, Default)]
+pub struct ProcessSnapshot {
+    pub pid: u32,
+    /// Resident set size in bytes
+    pub rss: u64,
+    /// Virtual memory in bytes
+    pub virtual_memory: u64,
+    /// CPU usage since the previous sample, 100 is one full core
+    pub cpu_percent: f64,
+    /// Thread count, where the platform reports it
+    pub threads: Option<u64>,
+    /// Seconds since the Unix epoch when the process started
+    pub start_time: u64,
+}
+
+impl SystemSnapshot {
+    /// Load average per CPU available to the service
+    ///
+    /// Uses the cgroup CPU quota when one is set.
+    pub fn cpu_load(&self) -> f64 {
+        let cpus = self
+            .cgroup
+            .as_ref()
+            .and_then(|cgroup| cgroup.cpu_quota)
+            .unwrap_or(self.cpu_count as f64);
+        if cpus > 0.0 {
+            self.load_average / cpus
+        } else {
+            0.0
+        }
+    }
+
+    /// Memory available to the service
+    ///
+    /// Reports the container's limit and working set when a cgroup memory
+    /// limit below host memory is set, and host memory otherwise.
+    pub fn memory(&self) -> MemoryInfo {
+        let limited = self.cgroup.as_ref().and_then(|cgroup| {
+            let limit = cgroup.memory_limit.filter(|l| *l < self.total_memory)?;
+            Some((limit, cgroup.memory_usage?))
+        });
+        let (total, used, source) = match limited {
+            Some((limit, usage)) => (limit, usage.min(limit), MemorySource::Cgroup),
+            None => (
+                self.total_memory,
+                self.total_memory.saturating_sub(self.available_memory),
+                MemorySource::Host,
+            ),
+        };
+        MemoryInfo {
+            total,
+            available: total - used,
+            used,
+            percent: if total > 0 {
+                (used as f64 / total as f64) * 100.0
+            } else {
+                0.0
+            },
+            source,
+        }
+    }
+
+    /// Seconds since the snapshot was taken
+    pub fn age(&self) -> f64 {
+        self.sampled_at.elapsed().as_secs_f64()
+    }
+}
+
+/// Keeps a cached [`SystemSnapshot`] so handlers never enumerate the host
+///
+/// Only memory, the CPU list, the service's own process and its cgroup are
+/// read, which avoids the process scan done by `System::new_all()`.
+pub struct SystemSampler {
+    system: Mutex<System>,
+    cgroup: Option<Cgroup>,
+    hostname: String,
+    event_loop_lag_us: AtomicU64,
+    snapshot: RwLock<Arc<SystemSnapshot>>,
+}
+
+impl SystemSampler {
+    /// Create a sampler holding an initial snapshot
+    pub fn new() -> Self {
+        Self::with_cgroup(Cgroup::detect())
+    }
+
+    /// Create a sampler reading container stats from the given cgroup
+    pub fn with_cgroup(cgroup: Option<Cgroup>) -> Self {
+        let mut system = System::new();
+        let hostname = System::host_name().unwrap_or_else(|| "unknown".to_string());
+        let snapshot = sample(&mut system, cgroup.as_ref(), &hostname, Duration::ZERO);
+        Self {
+            system: Mutex::new(system),
+            cgroup,
+            hostname,
+            event_loop_lag_us: AtomicU64::new(0),
+            snapshot: RwLock::new(Arc::new(snapshot)),
+        }
+    }
+
+    /// Most recent snapshot
+    pub fn snapshot(&self) -> Arc<SystemSnapshot> {
+        self.snapshot.read().unwrap().clone()
+    }
+
+    /// Record how late the runtime woke up for a timer
+    ///
+    /// The next snapshot reports the largest lag recorded until then.
+    pub fn record_event_loop_lag(&self, lag: Duration) {
+        self.event_loop_lag_us
+            .fetch_max(lag.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Take a new snapshot and make it the current one
+    pub fn refresh(&self) -> Arc<SystemSnapshot> {
+        let snapshot = Arc::new(sample(
+            &mut self.system.lock().unwrap(),
+            self.cgroup.as_ref(),
+            &self.hostname,
+            Duration::from_micros(self.event_loop_lag_us.swap(0, Ordering::Relaxed)),
+        ));
+        *self.snapshot.write().unwrap() = snapshot.clone();
+        snapshot
+    }
+}
+
+impl Default for SystemSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn sample(
+    system: &mut System,
+    cgroup: Option<&Cgroup>,
+    hostname: &str,
+    event_loop_lag: Duration,
+) -> SystemSnapshot {
+    system.refresh_memory();
+    system.refresh_cpu_list(CpuRefreshKind::new());
+    let pid = Pid::from_u32(std::process::id());
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::new().with_memory().with_cpu(),
+    );
+    let process = system
+        .process(pid)
+        .map(|process| ProcessSnapshot {
+            pid: pid.as_u32(),
+            rss: process.memory(),
+            virtual_memory: process.virtual_memory(),
+            cpu_percent: process.cpu_usage() as f64,
+            threads: threads(),
+            start_time: process.start_time(),
+        })
+        .unwrap_or_else(|| ProcessSnapshot {
+            pid: pid.as_u32(),
+            threads: threads(),
+            ..Default::default()
+        });
+
+    SystemSnapshot {
+        total_memory: system.total_memory(),
+        available_memory: system.available_memory(),
+        cpu_count: system.cpus().len(),
+        hostname: hostname.to_string(),
+        cgroup: cgroup.map(Cgroup::stats),
+        load_average: System::load_average().one,
+        open_fds: open_fds(),
+        event_loop_lag,
+        process,
+        sampled_at: Instant::now(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_fds() -> Option<u64> {
+    // The directory handle itself shows up in the listing
+    let entries = std::fs::read_dir("/proc/self/fd").ok()?.count() as u64;
+    Some(entries.saturating_sub(1))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_fds() -> Option<u64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn threads() -> Option<u64> {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("Threads:"))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn threads() -> Option<u64> {
+    None
+}
+
+/// How often the event loop lag is probed
+const LAG_PROBE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Refresh the system snapshot every `system.sample_interval` seconds
+///
+/// The interval is read again after every sample so reloads apply. Every
+/// snapshot is checked against the resource thresholds. A separate task
+/// measures the event loop lag every 100ms, and each snapshot reports the
+/// worst lag since the previous one, so short stalls are not missed.
+pub fn spawn_sampler(state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+    let probe = AbortOnDrop(tokio::spawn(probe_event_loop_lag(state.clone())));
+    tokio::spawn(async move {
+        // The probe stops with the sampler
+        let _probe = probe;
+        loop {
+            let interval = Duration::from_secs(state.config().system.sample_interval.max(1));
+            tokio::time::sleep(interval).await;
+
+            let sampler = state.clone();
+            match tokio::task::spawn_blocking(move || sampler.system.refresh()).await {
+                Ok(snapshot) => {
+                    let memory = snapshot.memory();
+                    debug!(
+                        "System: sampled {} of {} bytes memory in use",
+                        memory.used, memory.total
+                    );
+                    state
+                        .resources
+                        .evaluate(&snapshot, &state.config().health.thresholds);
+                    crate::metrics::record_system(&snapshot, state.uptime());
+                }
+                Err(e) => debug!("System: sampling failed: {}", e),
+            }
+        }
+    })
+}
+
+/// Measure how late the runtime wakes up for a short timer
+async fn probe_event_loop_lag(state: Arc<AppState>) {
+    loop {
+        let started = Instant::now();
+        tokio::time::sleep(LAG_PROBE_INTERVAL).await;
+        state
+            .system
+            .record_event_loop_lag(started.elapsed().saturating_sub(LAG_PROBE_INTERVAL));
+    }
+}
+
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
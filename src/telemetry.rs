@@ -4,29 +4,42 @@ use opentelemetry::global;
 use opentelemetry_otlp::WithExportConfig;
 #[cfg(feature = "telemetry")]
 use opentelemetry_sdk::{runtime, Resource};
+#[cfg(feature = "telemetry")]
+use std::sync::OnceLock;
 
+/// Tracer exporting the spans of the tracing layer
 #[cfg(feature = "telemetry")]
-pub fn init_tracer() -> Result<(), Box<dyn std::error::Error>> {
-    // Check if OTEL endpoint is configured
-    let otlp_endpoint =
-        std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").unwrap_or_else(|_| String::new());
+static TRACER: OnceLock<opentelemetry_sdk::trace::Tracer> = OnceLock::new();
 
-    if otlp_endpoint.is_empty() {
-        tracing::info!("[INFO] OpenTelemetry: OTEL_EXPORTER_OTLP_ENDPOINT not set, skipping OTLP configuration");
-        return Ok(());
-    }
+/// Outcome of [`init_tracer`], logged once tracing is set up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TracerStatus {
+    /// Built without the `telemetry` feature
+    Disabled,
+    /// `OTEL_EXPORTER_OTLP_ENDPOINT` is not set
+    NotConfigured,
+    /// Spans are exported to this OTLP endpoint
+    Exporting(String),
+}
 
-    tracing::info!(
-        "[INFO] OpenTelemetry: Configuring OTLP exporter with endpoint: {}",
-        otlp_endpoint
-    );
+/// Set up the OTLP span exporter
+///
+/// Runs before the tracing subscriber is installed so request spans can be
+/// exported through [`layer`].
+#[cfg(feature = "telemetry")]
+pub fn init_tracer() -> Result<TracerStatus, Box<dyn std::error::Error>> {
+    // Check if OTEL endpoint is configured
+    let Some(otlp_endpoint) = otlp_endpoint() else {
+        return Ok(TracerStatus::NotConfigured);
+    };
 
+    use opentelemetry::trace::TracerProvider as _;
     use opentelemetry_otlp::SpanExporter;
     use opentelemetry_sdk::trace::TracerProvider;
 
     let exporter = SpanExporter::builder()
         .with_tonic()
-        .with_endpoint(otlp_endpoint)
+        .with_endpoint(otlp_endpoint.clone())
         .build()?;
 
     let provider = TracerProvider::builder()
@@ -34,16 +47,58 @@ pub fn init_tracer() -> Result<(), Box<dyn std::error::Error>> {
         .with_resource(Resource::default())
         .build();
 
+    let _ = TRACER.set(provider.tracer("learn-rust"));
     global::set_tracer_provider(provider);
 
-    tracing::info!("[INFO] OpenTelemetry: Tracer initialized successfully");
-    Ok(())
+    Ok(TracerStatus::Exporting(otlp_endpoint))
+}
+
+#[cfg(not(feature = "telemetry"))]
+pub fn init_tracer() -> Result<TracerStatus, Box<dyn std::error::Error>> {
+    Ok(TracerStatus::Disabled)
+}
+
+/// Collector endpoint shared by the span and metrics exporters
+#[cfg(feature = "telemetry")]
+pub(crate) fn otlp_endpoint() -> Option<String> {
+    std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty())
+}
+
+/// Tracing layer exporting spans, once [`init_tracer`] configured an exporter
+#[cfg(feature = "telemetry")]
+pub fn layer<S>(
+) -> Option<tracing_opentelemetry::OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    TRACER
+        .get()
+        .map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer.clone()))
+}
+
+#[cfg(not(feature = "telemetry"))]
+pub fn layer() -> Option<tracing_subscriber::layer::Identity> {
+    None
+}
+
+/// Trace ID of the current span, when it is sampled and exported
+#[cfg(feature = "telemetry")]
+pub fn current_trace_id() -> Option<String> {
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let context = tracing::Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+    (span_context.is_valid() && span_context.is_sampled())
+        .then(|| span_context.trace_id().to_string())
 }
 
 #[cfg(not(feature = "telemetry"))]
-pub fn init_tracer() -> Result<(), Box<dyn std::error::Error>> {
-    tracing::info!("[INFO] OpenTelemetry: Telemetry feature not enabled");
-    Ok(())
+pub fn current_trace_id() -> Option<String> {
+    None
 }
 
 pub fn shutdown_tracer() {